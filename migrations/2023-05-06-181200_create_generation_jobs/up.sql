@@ -0,0 +1,21 @@
+CREATE TABLE generation_jobs
+(
+  id           uuid         not null default gen_random_uuid() primary key,
+  ingredients  text[]       not null,
+  -- pending, running, done or failed
+  status       varchar(20)  not null default 'pending',
+  error        text,
+  cocktail_ids uuid[]       not null default '{}',
+  created_at   timestamp    default current_timestamp,
+  updated_at   timestamp    default current_timestamp
+);
+
+CREATE INDEX generation_jobs_status_idx ON generation_jobs (status, created_at);
+
+CREATE TRIGGER create_generation_job_timestamps BEFORE INSERT
+  ON generation_jobs FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_insert();
+
+CREATE TRIGGER update_generation_job_timestamps BEFORE UPDATE
+  ON generation_jobs FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_update();
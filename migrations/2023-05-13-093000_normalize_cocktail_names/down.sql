@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`, merged cocktails stay merged
+DROP INDEX IF EXISTS unique_name_normalized;
+DROP FUNCTION IF EXISTS normalize_cocktail_name(text);
+ALTER TABLE cocktails ADD CONSTRAINT unique_name UNIQUE (name);
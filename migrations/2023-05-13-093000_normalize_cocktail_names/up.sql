@@ -0,0 +1,55 @@
+-- names only differing in case or whitespace ("Gin  sour", "GIN SOUR") are the same cocktail.
+-- Whitespace is what Rust's split_whitespace splits on, see similarity::normalize_name.
+CREATE OR REPLACE FUNCTION normalize_cocktail_name(raw text)
+  RETURNS text AS $$
+  SELECT UPPER(btrim(regexp_replace(
+    raw, '[\s\u0085\u00a0\u1680\u2000-\u200a\u2028\u2029\u202f\u205f\u3000]+', ' ', 'g'), ' '));
+$$ LANGUAGE sql IMMUTABLE;
+
+-- unique_name was case sensitive, so stored names may collide. The oldest cocktail of each
+-- name is kept. A recipe's ingredients and instructions belong together, so they are never mixed:
+-- a kept cocktail without ingredients takes both from its oldest duplicate that has some.
+CREATE TEMPORARY TABLE cocktail_merges AS
+SELECT id AS duplicate_id,
+       first_value(id) OVER (PARTITION BY normalize_cocktail_name(name) ORDER BY created_at, id) AS kept_id
+FROM cocktails;
+
+DELETE FROM cocktail_merges WHERE duplicate_id = kept_id;
+
+CREATE TEMPORARY TABLE cocktail_donors AS
+SELECT DISTINCT ON (m.kept_id) m.kept_id, m.duplicate_id
+FROM cocktail_merges m
+JOIN cocktails c ON c.id = m.duplicate_id
+WHERE NOT EXISTS (SELECT 1 FROM ingredients k WHERE k.cocktail_id = m.kept_id)
+  AND EXISTS (SELECT 1 FROM ingredients i WHERE i.cocktail_id = m.duplicate_id)
+ORDER BY m.kept_id, c.created_at, c.id;
+
+DELETE FROM instructions
+USING cocktail_donors d
+WHERE instructions.cocktail_id = d.kept_id;
+
+UPDATE ingredients SET cocktail_id = d.kept_id
+FROM cocktail_donors d
+WHERE ingredients.cocktail_id = d.duplicate_id;
+
+UPDATE instructions SET cocktail_id = d.kept_id
+FROM cocktail_donors d
+WHERE instructions.cocktail_id = d.duplicate_id;
+
+DROP TABLE cocktail_donors;
+
+UPDATE generation_jobs SET cocktail_ids = ARRAY(
+  SELECT COALESCE(m.kept_id, c.id)
+  FROM unnest(generation_jobs.cocktail_ids) WITH ORDINALITY AS c(id, n)
+  LEFT JOIN cocktail_merges m ON m.duplicate_id = c.id
+  ORDER BY c.n
+)
+WHERE cocktail_ids && ARRAY(SELECT duplicate_id FROM cocktail_merges);
+
+-- the other recipes go with them (on delete cascade)
+DELETE FROM cocktails WHERE id IN (SELECT duplicate_id FROM cocktail_merges);
+DROP TABLE cocktail_merges;
+
+ALTER TABLE cocktails DROP CONSTRAINT IF EXISTS unique_name;
+
+CREATE UNIQUE INDEX unique_name_normalized ON cocktails (normalize_cocktail_name(name));
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ingredients DROP COLUMN IF EXISTS canonical_ingredient_id;
+DROP TABLE ingredient_aliases;
+DROP TABLE canonical_ingredients;
+DROP FUNCTION IF EXISTS normalize_ingredient_name(text);
@@ -0,0 +1,69 @@
+-- lower case, single spaces and no "fresh" prefix so that "Fresh  Lemon juice" and
+-- "lemon juice" are the same ingredient
+CREATE OR REPLACE FUNCTION normalize_ingredient_name(raw text)
+  RETURNS text AS $$
+  SELECT regexp_replace(
+    lower(regexp_replace(btrim(raw), '\s+', ' ', 'g')),
+    '^(freshly squeezed|fresh squeezed|fresh) ', '');
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE TABLE canonical_ingredients
+(
+  id         uuid         not null default gen_random_uuid() primary key,
+  name       varchar(100) not null,
+  created_at timestamp    default current_timestamp,
+  updated_at timestamp    default current_timestamp
+);
+
+CREATE TRIGGER create_canonical_ingredient_timestamps BEFORE INSERT
+  ON canonical_ingredients FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_insert();
+
+CREATE TRIGGER update_canonical_ingredient_timestamps BEFORE UPDATE
+  ON canonical_ingredients FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_update();
+
+-- every spelling an ingredient is known by, including its canonical name,
+-- stored as normalize_ingredient_name(spelling)
+CREATE TABLE ingredient_aliases
+(
+  alias                   varchar(100) not null primary key,
+  canonical_ingredient_id uuid         not null references canonical_ingredients (id) on delete cascade,
+  created_at              timestamp    default current_timestamp,
+  updated_at              timestamp    default current_timestamp
+);
+
+CREATE INDEX ingredient_aliases_canonical_idx ON ingredient_aliases (canonical_ingredient_id);
+
+CREATE TRIGGER create_ingredient_alias_timestamps BEFORE INSERT
+  ON ingredient_aliases FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_insert();
+
+CREATE TRIGGER update_ingredient_alias_timestamps BEFORE UPDATE
+  ON ingredient_aliases FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_update();
+
+ALTER TABLE ingredients ADD COLUMN canonical_ingredient_id uuid
+  references canonical_ingredients (id) on delete set null;
+
+CREATE INDEX ingredients_canonical_idx ON ingredients (canonical_ingredient_id);
+
+-- backfill the catalogue from the ingredients we already have, using the
+-- most common spelling of each ingredient as its canonical name
+INSERT INTO canonical_ingredients (name)
+  SELECT DISTINCT ON (alias) spelling
+  FROM (
+    SELECT normalize_ingredient_name(name) AS alias,
+           regexp_replace(btrim(name), '\s+', ' ', 'g') AS spelling,
+           count(*) AS uses
+    FROM ingredients
+    GROUP BY 1, 2
+  ) spellings
+  ORDER BY alias, uses DESC, spelling;
+
+INSERT INTO ingredient_aliases (alias, canonical_ingredient_id)
+  SELECT normalize_ingredient_name(name), id FROM canonical_ingredients;
+
+UPDATE ingredients i SET canonical_ingredient_id = a.canonical_ingredient_id
+  FROM ingredient_aliases a
+  WHERE a.alias = normalize_ingredient_name(i.name);
@@ -0,0 +1 @@
+DROP INDEX ingredient_aliases_alias_trgm_idx;
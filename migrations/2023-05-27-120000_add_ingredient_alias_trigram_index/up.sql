@@ -0,0 +1,5 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- serves both the fuzzy (%) and the ILIKE lookups on ingredient names
+CREATE INDEX ingredient_aliases_alias_trgm_idx ON ingredient_aliases
+  USING gin (alias gin_trgm_ops);
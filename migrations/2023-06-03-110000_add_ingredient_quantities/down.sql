@@ -0,0 +1,4 @@
+ALTER TABLE ingredients
+  DROP COLUMN quantity,
+  DROP COLUMN quantity_max,
+  DROP COLUMN unit;
@@ -0,0 +1,6 @@
+-- structured copy of measurement, e.g. "1-2 dashes" is 1, 2, 'dash'.
+-- filled in by the app from the measurement text
+ALTER TABLE ingredients
+  ADD COLUMN quantity     float8,
+  ADD COLUMN quantity_max float8,
+  ADD COLUMN unit         varchar(20);
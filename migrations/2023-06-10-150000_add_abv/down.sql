@@ -0,0 +1,7 @@
+DROP INDEX cocktails_abv_idx;
+
+ALTER TABLE cocktails
+  DROP COLUMN abv,
+  DROP COLUMN standard_drinks;
+
+ALTER TABLE canonical_ingredients DROP COLUMN abv;
@@ -0,0 +1,9 @@
+-- percent alcohol by volume, NULL when unknown
+ALTER TABLE canonical_ingredients ADD COLUMN abv float8;
+
+-- estimated from the ingredients and method by the app whenever a cocktail is written
+ALTER TABLE cocktails
+  ADD COLUMN abv             float8,
+  ADD COLUMN standard_drinks float8;
+
+CREATE INDEX cocktails_abv_idx ON cocktails (abv);
@@ -0,0 +1,20 @@
+CREATE TABLE api_keys
+(
+  id          uuid         not null default gen_random_uuid() primary key,
+  name        varchar(100) not null,
+  -- sha256 of the key, hex encoded. The key itself is only shown when it is created
+  key_hash    varchar(64)  not null unique,
+  -- read, write and/or generate
+  scopes      text[]       not null default '{}',
+  revoked_at  timestamp,
+  created_at  timestamp    default current_timestamp,
+  updated_at  timestamp    default current_timestamp
+);
+
+CREATE TRIGGER create_api_key_timestamps BEFORE INSERT
+  ON api_keys FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_insert();
+
+CREATE TRIGGER update_api_key_timestamps BEFORE UPDATE
+  ON api_keys FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_update();
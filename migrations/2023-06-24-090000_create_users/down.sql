@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE todos DROP COLUMN created_by;
+ALTER TABLE cocktails DROP COLUMN created_by;
+DROP TABLE users;
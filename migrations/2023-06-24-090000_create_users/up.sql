@@ -0,0 +1,22 @@
+CREATE TABLE users
+(
+  id            uuid         not null default gen_random_uuid() primary key,
+  username      varchar(50)  not null,
+  -- argon2 PHC string
+  password_hash text         not null,
+  created_at    timestamp    default current_timestamp,
+  updated_at    timestamp    default current_timestamp
+);
+
+CREATE UNIQUE INDEX unique_username ON users (LOWER(username));
+
+CREATE TRIGGER create_user_timestamps BEFORE INSERT
+  ON users FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_insert();
+
+CREATE TRIGGER update_user_timestamps BEFORE UPDATE
+  ON users FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_update();
+
+ALTER TABLE cocktails ADD COLUMN created_by uuid REFERENCES users (id) ON DELETE SET NULL;
+ALTER TABLE todos ADD COLUMN created_by uuid REFERENCES users (id) ON DELETE SET NULL;
@@ -0,0 +1,18 @@
+-- LLM generations queued per API key, user or ip and day, to enforce the daily quota
+CREATE TABLE llm_usage
+(
+  subject      varchar(100) not null,
+  day          date         not null default current_date,
+  generations  integer      not null default 0,
+  created_at   timestamp    default current_timestamp,
+  updated_at   timestamp    default current_timestamp,
+  primary key (subject, day)
+);
+
+CREATE TRIGGER create_llm_usage_timestamps BEFORE INSERT
+  ON llm_usage FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_insert();
+
+CREATE TRIGGER update_llm_usage_timestamps BEFORE UPDATE
+  ON llm_usage FOR EACH ROW EXECUTE PROCEDURE
+  handle_timestamps_on_row_update();
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX generation_jobs_pending_ingredients_idx;
+ALTER TABLE generation_jobs DROP COLUMN claimed_by;
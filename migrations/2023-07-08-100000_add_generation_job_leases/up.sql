@@ -0,0 +1,19 @@
+-- set to a new id by the worker that claims a job, which keeps updated_at fresh while it runs
+ALTER TABLE generation_jobs ADD COLUMN claimed_by uuid;
+
+-- ingredients are stored trimmed, lowercased and sorted by bytes like job_ingredients does from
+-- now on, one pending job per set of them is enough
+UPDATE generation_jobs SET ingredients = ARRAY(
+  SELECT DISTINCT lower(btrim(x)) COLLATE "C" AS y FROM unnest(ingredients) x ORDER BY y
+)
+WHERE status = 'pending';
+
+UPDATE generation_jobs SET status = 'failed', error = 'Queued twice for the same ingredients'
+WHERE status = 'pending' AND id NOT IN (
+  SELECT DISTINCT ON (ingredients) id FROM generation_jobs
+  WHERE status = 'pending'
+  ORDER BY ingredients, created_at
+);
+
+CREATE UNIQUE INDEX generation_jobs_pending_ingredients_idx ON generation_jobs (ingredients)
+WHERE status = 'pending';
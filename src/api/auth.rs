@@ -0,0 +1,218 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::web::Data;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use uuid::Uuid;
+
+use crate::config;
+use crate::error::ApiError;
+use crate::models::api_keys::{ApiKey, Scope};
+use crate::models::users::User;
+use crate::repository::database::Database;
+
+/// The logged in user making a request, from the token they got on login. Take an
+/// `Option<CurrentUser>` where requests made with an API key or without logging in are fine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurrentUser {
+    pub id: Uuid,
+    pub username: String,
+}
+
+impl CurrentUser {
+    fn from_token(token: &str) -> Result<CurrentUser, ApiError> {
+        let claims = User::verify_token(token)?;
+        Ok(CurrentUser { id: claims.sub, username: claims.username })
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = ApiError;
+    type Future = Ready<Result<CurrentUser, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // checked already when the route is behind the validator
+        if let Some(user) = req.extensions().get::<CurrentUser>() {
+            return ready(Ok(user.clone()));
+        }
+
+        let token = req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .filter(|x| !ApiKey::is_api_key(x));
+
+        ready(match token {
+            Some(token) => CurrentUser::from_token(token),
+            None => Err(ApiError::Unauthorized("Log in and send the token as Authorization: Bearer <token>".to_string())),
+        })
+    }
+}
+
+/// Whether requests that only read may be made without an API key (`auth.public_reads`)
+pub fn public_reads() -> bool {
+    config::get().auth.public_reads
+}
+
+/// Whether anyone may create an account (`auth.open_registration`). Users can do everything
+/// once logged in, so by default registering takes an API key with the write scope.
+pub fn open_registration() -> bool {
+    config::get().auth.open_registration
+}
+
+/// The API key `req` was sent with if it has `scope`, for routes outside the validator
+pub fn require_api_key(req: &HttpRequest, db: &Database, scope: Scope) -> Result<ApiKey, ApiError> {
+    let token = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .filter(|x| ApiKey::is_api_key(x))
+        .ok_or_else(|| ApiError::Unauthorized(format!("An API key with the {} scope is required as Authorization: Bearer <key>", scope)))?;
+
+    match ApiKey::find_by_key(db, token)? {
+        Some(api_key) if api_key.has_scope(scope) => Ok(api_key),
+        Some(api_key) => Err(ApiError::Forbidden(format!("API key {:?} doesn't have the {} scope", api_key.name, scope))),
+        None => Err(ApiError::Unauthorized("Invalid API key".to_string())),
+    }
+}
+
+/// The scope an API key needs for `req`
+pub fn required_scope(req: &ServiceRequest) -> Scope {
+    // a GET, but it queues LLM calls
+    if req.path().ends_with("/cocktails/generate") {
+        return Scope::Generate;
+    }
+
+    match *req.method() {
+        Method::GET | Method::HEAD | Method::OPTIONS => Scope::Read,
+        _ => Scope::Write,
+    }
+}
+
+/// Checks the `Authorization: Bearer <key>` of requests to the api scopes, see `Authentication`.
+/// Either an API key or the token of a logged in user, who can do anything. The key or user is
+/// put in the request extensions for handlers that need to know who is asking.
+pub async fn validator(
+    req: ServiceRequest,
+    credentials: Option<BearerAuth>,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let scope = required_scope(&req);
+
+    let credentials = match credentials {
+        Some(credentials) => credentials,
+        None if scope == Scope::Read && public_reads() => return Ok(req),
+        None => {
+            let err = ApiError::Unauthorized("An API key or login token is required as Authorization: Bearer <token>".to_string());
+            return Err((err.into(), req));
+        },
+    };
+
+    if !ApiKey::is_api_key(credentials.token()) {
+        return match CurrentUser::from_token(credentials.token()) {
+            Ok(user) => {
+                req.extensions_mut().insert(user);
+                Ok(req)
+            },
+            Err(err) => Err((err.into(), req)),
+        };
+    }
+
+    let api_key = match req.app_data::<Data<Database>>() {
+        Some(db) => ApiKey::find_by_key(db, credentials.token()),
+        None => Err(ApiError::Internal("No database configured".to_string())),
+    };
+
+    match api_key {
+        Ok(Some(api_key)) if api_key.has_scope(scope) => {
+            req.extensions_mut().insert(api_key);
+            Ok(req)
+        },
+        Ok(Some(api_key)) => {
+            let err = ApiError::Forbidden(format!("API key {:?} doesn't have the {} scope", api_key.name, scope));
+            Err((err.into(), req))
+        },
+        Ok(None) => Err((ApiError::Unauthorized("Invalid API key".to_string()).into(), req)),
+        Err(err) => Err((err.into(), req)),
+    }
+}
+
+/// Runs `validator` for the api scopes, `.wrap(auth::Authentication)`. Unlike
+/// `HttpAuthentication` it answers rejected requests itself instead of returning an error, which
+/// CORS would pass on without its headers and browsers would report as a network error.
+pub struct Authentication;
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let credentials = req.extract::<Option<BearerAuth>>().await.ok().flatten();
+            match validator(req, credentials).await {
+                Ok(req) => Ok(service.call(req).await?.map_into_left_body()),
+                Err((err, req)) => Ok(req.error_response(err).map_into_right_body()),
+            }
+        })
+    }
+}
+
+
+#[actix_web::test]
+pub async fn test_unauthorized_has_cors_headers() {
+    use actix_web::{test, web, App, HttpResponse};
+
+    use crate::config::CorsConfig;
+
+    let cors = CorsConfig { allowed_origins: vec!["http://localhost:3000".to_string()], ..CorsConfig::default() };
+    let app = test::init_service(
+        App::new()
+            .service(
+                web::scope("/bartender")
+                    .wrap(Authentication)
+                    .route("/cocktails", web::get().to(HttpResponse::Ok))
+            )
+            .wrap(crate::api::cors::cors(&cors))
+    ).await;
+
+    let req = test::TestRequest::get()
+        .uri("/bartender/cocktails")
+        .insert_header((header::ORIGIN, "http://localhost:3000"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), 401);
+    assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "http://localhost:3000");
+}
@@ -1,25 +1,72 @@
 use std::fmt;
 
-use actix_web::{web, delete};
+use actix_web::{web, delete, route};
 use actix_web::{web::{
     Data,
     Json,
     Path,
     Query,
-}, get, post, HttpResponse};
+}, get, post, HttpRequest, HttpResponse};
+use chrono::NaiveDate;
 use serde::de::IntoDeserializer;
 use serde::{Deserialize, de};
 use uuid::Uuid;
 
-use crate::models::cocktails::CocktailData;
+use crate::api::auth::{self, CurrentUser};
+use crate::api::rate_limit::{self, RateLimit};
+use crate::error::ApiError;
+use crate::models::batches::{BatchRecipe, BatchTarget};
+use crate::models::cocktails::{CocktailData, CocktailFilters, CocktailInclude, CocktailSort};
+use crate::models::generation_jobs::GenerationJob;
+use crate::models::measurements::UnitSystem;
 use crate::{models::cocktails::Cocktail, repository::database::Database};
 
 #[derive(Deserialize)]
 pub struct GenerateQuery {
     #[serde(deserialize_with = "deserialize_stringified_list")]
     pub ingredients: Vec<String>,
-    pub pagestart: Option<u32>,
+    /// `cursor` of the previous page
+    pub cursor: Option<String>,
     pub pagesize: Option<u32>,
+    /// minimum number of requested ingredients a cocktail has to contain
+    pub min_matches: Option<i64>,
+    /// minimum share (0.0 - 1.0) of a cocktail's ingredients that have to be requested
+    pub min_coverage: Option<f64>,
+    /// render amounts in metric or imperial units instead of as stored
+    pub units: Option<UnitSystem>,
+}
+
+/// Filters on top of the generated `CocktailFilters` (name, page, per_page)
+#[derive(Deserialize)]
+pub struct CocktailsQuery {
+    /// only cocktails at most this strong (percent ABV)
+    pub max_abv: Option<f64>,
+    /// only cocktails created on or after this day
+    pub created_from: Option<NaiveDate>,
+    /// only cocktails created on or before this day
+    pub created_to: Option<NaiveDate>,
+    /// only cocktails with all of these ingredients
+    #[serde(default, deserialize_with = "deserialize_stringified_list")]
+    pub ingredients: Vec<String>,
+    pub sort: Option<CocktailSort>,
+    /// e.g. "ingredients,instructions" to have them in the list
+    #[serde(default, deserialize_with = "deserialize_stringified_list")]
+    pub include: Vec<CocktailInclude>,
+}
+
+#[derive(Deserialize)]
+pub struct UnitsQuery {
+    /// render amounts in metric or imperial units instead of as stored
+    pub units: Option<UnitSystem>,
+}
+
+#[derive(Deserialize)]
+pub struct ScaleQuery {
+    pub servings: Option<u32>,
+    /// e.g. "1l", "750ml" or "32oz"
+    pub total_volume: Option<String>,
+    /// render amounts in metric or imperial units instead of as stored
+    pub units: Option<UnitSystem>,
 }
 
 // https://github.com/actix/actix-web/issues/1301#issuecomment-747403932
@@ -54,38 +101,84 @@ where
     deserializer.deserialize_any(StringVecNames(std::marker::PhantomData::<I>))
 }
 
+/// `req`'s url with the page parameter set to `page`
+fn page_link(req: &HttpRequest, page: i64) -> String {
+    let mut params: Vec<&str> = req.query_string()
+        .split('&')
+        .filter(|x| !x.is_empty() && !x.starts_with("page="))
+        .collect();
+    let page_param = format!("page={}", page);
+    params.push(&page_param);
+    format!("{}?{}", req.path(), params.join("&"))
+}
+
 #[get("/cocktails")]
-pub async fn get_cocktails(db: Data<Database>) -> HttpResponse {
-    let todos = Cocktail::get_cocktails(&db);
-    HttpResponse::Ok().json(todos)
+pub async fn get_cocktails(
+    db: Data<Database>,
+    req: HttpRequest,
+    filters: Query<CocktailFilters>,
+    query: Query<CocktailsQuery>
+) -> Result<HttpResponse, ApiError> {
+    let mut cocktails = Cocktail::get_cocktails(&db, &filters, &query)?;
+    cocktails.next = cocktails.next_page().map(|x| page_link(&req, x));
+    cocktails.prev = cocktails.prev_page().map(|x| page_link(&req, x));
+    Ok(HttpResponse::Ok().json(cocktails))
 }
 
 #[post("/cocktails")]
 // #[tracing::instrument]
-pub async fn create_cocktail(db: Data<Database>, new_cocktail: Json<CocktailData>) -> HttpResponse {
-    let todo = Cocktail::create_cocktail(&db, new_cocktail.into_inner());
-    match todo {
-        Ok(todo) => HttpResponse::Ok().json(todo),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-    }
+pub async fn create_cocktail(
+    db: Data<Database>,
+    user: Option<CurrentUser>,
+    new_cocktail: Json<CocktailData>
+) -> Result<HttpResponse, ApiError> {
+    let cocktail = Cocktail::create_cocktail(&db, new_cocktail.into_inner(), user.map(|x| x.id))?;
+    Ok(HttpResponse::Ok().json(cocktail))
 }
 
 #[get("/cocktails/{id}")]
-pub async fn get_cocktail_by_id(db: Data<Database>, id: Path<Uuid>) -> HttpResponse {
-    let cocktail = Cocktail::get_cocktail_by_id(&db, id.into_inner());
-    match cocktail {
-        Some(cocktail) => HttpResponse::Ok().json(cocktail),
-        None => HttpResponse::NotFound().body("Todo not found")
+pub async fn get_cocktail_by_id(
+    db: Data<Database>,
+    id: Path<Uuid>,
+    query: Query<UnitsQuery>
+) -> Result<HttpResponse, ApiError> {
+    let mut cocktail = Cocktail::get_cocktail_by_id(&db, id.into_inner())?;
+    if let Some(units) = query.units {
+        cocktail.convert_units(units);
     }
+    Ok(HttpResponse::Ok().json(cocktail))
 }
 
-#[delete("/cocktails/{id}")]
-pub async fn delete_cocktail_by_id(db: Data<Database>, id: Path<Uuid>) -> HttpResponse {
-    let deleted = Cocktail::delete_cocktail(&db, id.into_inner());
-    match deleted {
-        Some(del) => HttpResponse::Ok().json(del),
-        None => HttpResponse::NotFound().body("Todo not found"),
+#[get("/cocktails/{id}/scale")]
+pub async fn scale_cocktail_by_id(
+    db: Data<Database>,
+    id: Path<Uuid>,
+    query: Query<ScaleQuery>
+) -> Result<HttpResponse, ApiError> {
+    let target = BatchTarget::new(query.servings, query.total_volume.as_deref())?;
+    let mut cocktail = Cocktail::get_cocktail_by_id(&db, id.into_inner())?;
+    // converted first so that the batch is rounded in the units it is poured in
+    if let Some(units) = query.units {
+        cocktail.convert_units(units);
     }
+    let batch = BatchRecipe::new(cocktail, target)?;
+    Ok(HttpResponse::Ok().json(batch))
+}
+
+#[route("/cocktails/{id}", method = "PUT", method = "PATCH")]
+pub async fn update_cocktail_by_id(
+    db: Data<Database>,
+    id: Path<Uuid>,
+    updated_cocktail: Json<CocktailData>
+) -> Result<HttpResponse, ApiError> {
+    let cocktail = Cocktail::update_cocktail(&db, id.into_inner(), updated_cocktail.into_inner())?;
+    Ok(HttpResponse::Ok().json(cocktail))
+}
+
+#[delete("/cocktails/{id}")]
+pub async fn delete_cocktail_by_id(db: Data<Database>, id: Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let deleted = Cocktail::delete_cocktail(&db, id.into_inner())?;
+    Ok(HttpResponse::Ok().json(deleted))
 }
 
 // generate cocktail endpoint
@@ -95,25 +188,46 @@ pub async fn delete_cocktail_by_id(db: Data<Database>, id: Path<Uuid>) -> HttpRe
 // 2. order by cocktail_id
 // 3. order by count and percentage of ingredients in cocktail (if 4 out of 5 ingredients are in
 //    ingredient list passed to genereate endpoint add cocktail to return list of cocktails)
-// 4. If percentage of cocktail is completed is less than 25% or 1 ingredinet out of
-//    the total required for the cocktail. Ask chat gpt for more.
+// 4. Page through them with the returned cursor. Once they run out ask chat gpt for more,
+//    the pages after that return what it came up with.
 #[get("/cocktails/generate")]
-pub async fn generate_cocktails(db: Data<Database>, query: Query<GenerateQuery>) -> HttpResponse {
-    println!("[cocktails] generate_cocktails");
-    let cocktails = Cocktail::generate_cocktails(&db, &query);
-    match cocktails {
-        Ok(c) => HttpResponse::Ok().json(c),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+pub async fn generate_cocktails(
+    db: Data<Database>,
+    req: HttpRequest,
+    query: Query<GenerateQuery>
+) -> Result<HttpResponse, ApiError> {
+    let mut cocktails = Cocktail::generate_cocktails(&db, &query, &rate_limit::subject(&req))?;
+    if let Some(units) = query.units {
+        cocktails.cocktails.iter_mut().for_each(|x| x.cocktail.convert_units(units));
+    }
+    Ok(HttpResponse::Ok().json(cocktails))
+}
+
+#[get("/generation-jobs/{id}")]
+pub async fn get_generation_job_by_id(
+    db: Data<Database>,
+    id: Path<Uuid>,
+    query: Query<UnitsQuery>
+) -> Result<HttpResponse, ApiError> {
+    let mut job = GenerationJob::get_job_by_id(&db, id.into_inner())?;
+    if let Some(units) = query.units {
+        job.cocktails.iter_mut().for_each(|x| x.cocktail.convert_units(units));
     }
+    Ok(HttpResponse::Ok().json(job))
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/bartender")
+            .wrap(RateLimit)
+            .wrap(auth::Authentication)
             .service(get_cocktails)
             .service(create_cocktail)
             .service(generate_cocktails)
             .service(get_cocktail_by_id)
+            .service(scale_cocktail_by_id)
+            .service(update_cocktail_by_id)
             .service(delete_cocktail_by_id)
+            .service(get_generation_job_by_id)
     );
 }
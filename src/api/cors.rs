@@ -0,0 +1,30 @@
+use actix_cors::Cors;
+
+use crate::config::CorsConfig;
+
+/// Whether any origin may make cross origin requests, `.wrap(Condition::new(enabled, cors))`
+pub fn enabled(config: &CorsConfig) -> bool {
+    !config.allowed_origins.is_empty()
+}
+
+/// CORS for the origins, methods and headers of `config`. Preflight requests are answered
+/// before they get to the auth validator.
+pub fn cors(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(config.allowed_methods.iter().map(|x| x.as_str()))
+        .allowed_headers(config.allowed_headers.iter().map(|x| x.as_str()))
+        .expose_headers(config.expose_headers.iter().map(|x| x.as_str()))
+        .max_age(config.max_age_secs);
+
+    if config.allowed_origins.iter().any(|x| x == "*") {
+        cors = cors.allow_any_origin();
+    } else {
+        for origin in &config.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
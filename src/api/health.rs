@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use actix_web::web::{self, Data};
+use actix_web::{get, HttpResponse};
+
+use crate::error::ApiError;
+use crate::llm::CocktailSuggester;
+use crate::models::health::Readiness;
+use crate::repository::database::Database;
+use crate::Response;
+
+/// The LLM provider readiness checks, `llm.health_check`
+pub struct LlmHealthCheck(pub Option<Arc<dyn CocktailSuggester>>);
+
+/// Whether the process is up, doesn't touch anything so that a slow database doesn't get it
+/// restarted
+pub async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(Response { message: "Everything is working fine".to_string() })
+}
+
+/// Whether requests can be served, 503 with the components that are down otherwise
+#[get("/ready")]
+pub async fn ready(db: Data<Database>, llm: Data<LlmHealthCheck>) -> Result<HttpResponse, ApiError> {
+    // the checks wait on connections and the provider, off the workers
+    let readiness = web::block(move || Readiness::check(&db, llm.0.as_deref()))
+        .await
+        .map_err(|err| ApiError::Internal(format!("Readiness check failed: {}", err)))?;
+
+    if readiness.ready {
+        Ok(HttpResponse::Ok().json(readiness))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(readiness))
+    }
+}
+
+// public, probes don't have a key
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/health")
+            // /health was there before the probes
+            .route("", web::get().to(live))
+            .route("/live", web::get().to(live))
+            .service(ready)
+    );
+}
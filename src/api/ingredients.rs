@@ -0,0 +1,77 @@
+use actix_web::web;
+use actix_web::{web::{
+    Data,
+    Json,
+    Path,
+    Query,
+}, get, patch, post, HttpResponse};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::auth;
+use crate::api::rate_limit::RateLimit;
+use crate::error::ApiError;
+use crate::models::canonical_ingredients::{CanonicalIngredient, CanonicalIngredientUpdate, NewAlias};
+use crate::repository::database::Database;
+
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[get("/suggest")]
+pub async fn suggest_ingredients(db: Data<Database>, query: Query<SuggestQuery>) -> Result<HttpResponse, ApiError> {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::BadRequest("q must not be empty".to_string()));
+    }
+
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+    let suggestions = CanonicalIngredient::suggest(&db, &query.q, limit)?;
+    Ok(HttpResponse::Ok().json(suggestions))
+}
+
+#[get("/canonical")]
+pub async fn get_canonical_ingredients(db: Data<Database>) -> Result<HttpResponse, ApiError> {
+    let canonicals = CanonicalIngredient::get_canonical_ingredients(&db)?;
+    Ok(HttpResponse::Ok().json(canonicals))
+}
+
+#[get("/canonical/{id}")]
+pub async fn get_canonical_ingredient_by_id(db: Data<Database>, id: Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let canonical = CanonicalIngredient::get_canonical_ingredient_by_id(&db, id.into_inner())?;
+    Ok(HttpResponse::Ok().json(canonical))
+}
+
+#[patch("/canonical/{id}")]
+pub async fn update_canonical_ingredient_by_id(
+    db: Data<Database>,
+    id: Path<Uuid>,
+    update: Json<CanonicalIngredientUpdate>
+) -> Result<HttpResponse, ApiError> {
+    let canonical = CanonicalIngredient::update_canonical_ingredient(&db, id.into_inner(), update.into_inner())?;
+    Ok(HttpResponse::Ok().json(canonical))
+}
+
+#[post("/canonical/{id}/aliases")]
+pub async fn add_canonical_ingredient_alias(
+    db: Data<Database>,
+    id: Path<Uuid>,
+    new_alias: Json<NewAlias>
+) -> Result<HttpResponse, ApiError> {
+    let canonical = CanonicalIngredient::add_alias(&db, id.into_inner(), &new_alias.alias)?;
+    Ok(HttpResponse::Ok().json(canonical))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/bartender/ingredients")
+            .wrap(RateLimit)
+            .wrap(auth::Authentication)
+            .service(suggest_ingredients)
+            .service(get_canonical_ingredients)
+            .service(get_canonical_ingredient_by_id)
+            .service(update_canonical_ingredient_by_id)
+            .service(add_canonical_ingredient_alias)
+    );
+}
@@ -1,2 +1,8 @@
+pub mod auth;
+pub mod cors;
+pub mod health;
+pub mod rate_limit;
 pub mod todos;
 pub mod cocktails;
+pub mod ingredients;
+pub mod users;
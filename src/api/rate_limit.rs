@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::web::Data;
+use actix_web::http::header::X_FORWARDED_FOR;
+use actix_web::{Error, HttpMessage, HttpRequest};
+
+use crate::api::auth::CurrentUser;
+use crate::config::{self, RateLimitConfig};
+use crate::error::ApiError;
+use crate::models::api_keys::ApiKey;
+
+/// Most buckets kept. Once reached full ones are dropped, they'd be the same as new ones, and
+/// then the longest unused until a tenth is free again.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Who a request counts against: its API key, the logged in user or else the client's ip
+pub fn subject(req: &HttpRequest) -> String {
+    if let Some(api_key) = req.extensions().get::<ApiKey>() {
+        return format!("key:{}", api_key.id);
+    }
+    if let Some(user) = req.extensions().get::<CurrentUser>() {
+        return format!("user:{}", user.id);
+    }
+    match client_ip(req) {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// The ip of the client. Clients can send X-Forwarded-For themselves, so it's only believed
+/// when the request comes from one of `server.trusted_proxies`.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let trusted: Vec<IpAddr> = config::get().server.trusted_proxies.iter()
+        .filter_map(|x| x.parse().ok())
+        .collect();
+    let forwarded: Vec<&str> = req.headers()
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|x| x.to_str().ok())
+        .collect();
+    Some(forwarded_client_ip(req.peer_addr()?.ip(), &forwarded, &trusted))
+}
+
+/// Proxies append who they got the request from, so going from the right the first ip that
+/// isn't a trusted proxy is the client
+fn forwarded_client_ip(peer: IpAddr, forwarded: &[&str], trusted: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    if !trusted.contains(&client) {
+        return client;
+    }
+
+    let hops = forwarded.iter().flat_map(|x| x.split(',')).collect::<Vec<&str>>();
+    for hop in hops.into_iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !trusted.contains(&ip) {
+                    break;
+                }
+            },
+            // whatever is further left can't be trusted either
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket per subject. Each request takes a token, tokens come back at `per_second`
+/// up to `capacity`.
+pub struct RateLimiter {
+    capacity: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: f64, burst: f64) -> RateLimiter {
+        RateLimiter {
+            capacity: burst.max(1.0),
+            per_second: per_minute / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The burst defaults to the per minute rate
+    pub fn from_config(config: &RateLimitConfig) -> RateLimiter {
+        RateLimiter::new(config.per_minute, config.burst.unwrap_or(config.per_minute))
+    }
+
+    /// Takes a token for `subject`, or returns the seconds until there is one
+    pub fn check(&self, subject: &str) -> Result<(), u64> {
+        self.check_at(subject, Instant::now())
+    }
+
+    fn prune(&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        let (capacity, per_second) = (self.capacity, self.per_second);
+        buckets.retain(|_, x| {
+            x.tokens + now.saturating_duration_since(x.refilled_at).as_secs_f64() * per_second < capacity
+        });
+
+        let keep = MAX_BUCKETS - MAX_BUCKETS / 10;
+        if buckets.len() > keep {
+            let mut used_at: Vec<Instant> = buckets.values().map(|x| x.refilled_at).collect();
+            let nth = used_at.len() - keep - 1;
+            let (_, cutoff, _) = used_at.select_nth_unstable(nth);
+            let cutoff = *cutoff;
+            buckets.retain(|_, x| x.refilled_at > cutoff);
+        }
+    }
+
+    fn check_at(&self, subject: &str, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|x| x.into_inner());
+
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(subject) {
+            self.prune(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(subject.to_string())
+            .or_insert(Bucket { tokens: self.capacity, refilled_at: now });
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / self.per_second).ceil() as u64)
+        }
+    }
+}
+
+/// Middleware answering 429 once a subject runs out of tokens. Wrap it inside the auth
+/// validator so that the key or user is known: `.wrap(RateLimit).wrap(auth::Authentication)`.
+/// Uses the `Data<RateLimiter>` of the app and lets everything through without one.
+pub struct RateLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let checked = match req.app_data::<Data<RateLimiter>>() {
+            Some(limiter) => limiter.check(&subject(req.request())),
+            None => Ok(()),
+        };
+
+        match checked {
+            Ok(()) => {
+                let fut = self.service.call(req);
+                Box::pin(async move { Ok(fut.await?.map_into_left_body()) })
+            },
+            // a response rather than an error, so that the middlewares further out (CORS) see it
+            Err(retry_after) => {
+                let err = ApiError::TooManyRequests(
+                    format!("Too many requests, try again in {} seconds", retry_after),
+                    retry_after,
+                );
+                Box::pin(ready(Ok(req.error_response(err).map_into_right_body())))
+            },
+        }
+    }
+}
+
+
+#[test]
+pub fn test_rate_limiter() {
+    use std::time::Duration;
+
+    // a token every 2 seconds, 3 at once
+    let limiter = RateLimiter::new(30.0, 3.0);
+    let start = Instant::now();
+
+    assert!(limiter.check_at("a", start).is_ok());
+    assert!(limiter.check_at("a", start).is_ok());
+    assert!(limiter.check_at("a", start).is_ok());
+    assert_eq!(limiter.check_at("a", start), Err(2));
+    // others have their own bucket
+    assert!(limiter.check_at("b", start).is_ok());
+
+    assert_eq!(limiter.check_at("a", start + Duration::from_secs(1)), Err(1));
+    assert!(limiter.check_at("a", start + Duration::from_secs(2)).is_ok());
+    assert!(limiter.check_at("a", start + Duration::from_secs(2)).is_err());
+
+    // a request each from more clients than there are buckets for
+    for i in 0..MAX_BUCKETS * 2 {
+        assert!(limiter.check_at(&i.to_string(), start + Duration::from_millis(i as u64)).is_ok());
+    }
+    let buckets = limiter.buckets.lock().unwrap();
+    assert!(buckets.len() <= MAX_BUCKETS);
+    assert!(buckets.contains_key(&(MAX_BUCKETS * 2 - 1).to_string()));
+}
+
+#[test]
+pub fn test_forwarded_client_ip() {
+    let ip = |x: &str| x.parse::<IpAddr>().unwrap();
+    let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+
+    // straight from a client, whatever it claims
+    assert_eq!(forwarded_client_ip(ip("1.2.3.4"), &["5.6.7.8"], &proxies), ip("1.2.3.4"));
+    // through both proxies, with a made up entry on the left
+    assert_eq!(forwarded_client_ip(ip("10.0.0.1"), &["5.6.7.8, 1.2.3.4", "10.0.0.2"], &proxies), ip("1.2.3.4"));
+    assert_eq!(forwarded_client_ip(ip("10.0.0.1"), &["garbage, 10.0.0.2"], &proxies), ip("10.0.0.2"));
+    assert_eq!(forwarded_client_ip(ip("10.0.0.1"), &[], &proxies), ip("10.0.0.1"));
+}
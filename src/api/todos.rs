@@ -5,31 +5,32 @@ use actix_web::{web::{
     Path,
 }, get, post, put, delete,  HttpResponse};
 
+use crate::api::auth::{self, CurrentUser};
+use crate::api::rate_limit::RateLimit;
+use crate::error::ApiError;
 use crate::{models::todo::Todo, repository::database::Database};
 
 #[get("")]
-pub async fn get_todos(db: Data<Database>) -> HttpResponse {
-    let todos = Todo::get_todos(&db);
-    HttpResponse::Ok().json(todos) 
+pub async fn get_todos(db: Data<Database>) -> Result<HttpResponse, ApiError> {
+    let todos = Todo::get_todos(&db)?;
+    Ok(HttpResponse::Ok().json(todos))
 }
 
 #[post("")]
 // #[tracing::instrument]
-pub async fn create_todo(db: Data<Database>, new_todo: Json<Todo>) -> HttpResponse {
-    let todo = Todo::create_todo(&db, new_todo.into_inner());
-    match todo {
-        Ok(todo) => HttpResponse::Ok().json(todo),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-    }
+pub async fn create_todo(
+    db: Data<Database>,
+    user: Option<CurrentUser>,
+    new_todo: Json<Todo>
+) -> Result<HttpResponse, ApiError> {
+    let todo = Todo::create_todo(&db, new_todo.into_inner(), user.map(|x| x.id))?;
+    Ok(HttpResponse::Ok().json(todo))
 }
 
 #[get("/{id}")]
-pub async fn get_todo_by_id(db: Data<Database>, id: Path<String>) -> HttpResponse {
-    let todo = Todo::get_todo_by_id(&db, &id);
-    match todo {
-        Some(todo) => HttpResponse::Ok().json(todo),
-        None => HttpResponse::NotFound().body("Todo not found")
-    }
+pub async fn get_todo_by_id(db: Data<Database>, id: Path<String>) -> Result<HttpResponse, ApiError> {
+    let todo = Todo::get_todo_by_id(&db, &id)?;
+    Ok(HttpResponse::Ok().json(todo))
 }
 
 #[put("/{id}")]
@@ -37,27 +38,23 @@ pub async fn update_todo_by_id(
     db: Data<Database>,
     id: Path<String>, 
     updated_todo: Json<Todo>
-) -> HttpResponse {
-    let todo = Todo::update_todo_by_id(&db, &id, updated_todo.into_inner());
-    match todo {
-        Some(todo) => HttpResponse::Ok().json(todo),
-        None => HttpResponse::NotFound().body("Todo not found"),
-    }
+) -> Result<HttpResponse, ApiError> {
+    let todo = Todo::update_todo_by_id(&db, &id, updated_todo.into_inner())?;
+    Ok(HttpResponse::Ok().json(todo))
 }
 
 #[delete("/{id}")]
-pub async fn delete_todo_by_id(db: Data<Database>, id: Path<String>) -> HttpResponse {
-    let deleted = Todo::delete_todo_by_id(&db, &id);
-    match deleted {
-        Some(del) => HttpResponse::Ok().json(del),
-        None => HttpResponse::NotFound().body("Todo not found"),
-    }
+pub async fn delete_todo_by_id(db: Data<Database>, id: Path<String>) -> Result<HttpResponse, ApiError> {
+    let deleted = Todo::delete_todo_by_id(&db, &id)?;
+    Ok(HttpResponse::Ok().json(deleted))
 }
 
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/todos")
+            .wrap(RateLimit)
+            .wrap(auth::Authentication)
             .service(get_todos)
             .service(create_todo)
             .service(get_todo_by_id)
@@ -0,0 +1,44 @@
+use actix_web::web;
+use actix_web::{web::{
+    Data,
+    Json,
+}, get, post, HttpRequest, HttpResponse};
+
+use crate::api::auth::{self, CurrentUser};
+use crate::api::rate_limit::RateLimit;
+use crate::error::ApiError;
+use crate::models::api_keys::Scope;
+use crate::models::users::{Credentials, User};
+use crate::repository::database::Database;
+
+#[post("/register")]
+pub async fn register(req: HttpRequest, db: Data<Database>, credentials: Json<Credentials>) -> Result<HttpResponse, ApiError> {
+    if !auth::open_registration() {
+        auth::require_api_key(&req, &db, Scope::Write)?;
+    }
+    let user = User::register(&db, credentials.into_inner())?;
+    Ok(HttpResponse::Created().json(user))
+}
+
+#[post("/login")]
+pub async fn login(db: Data<Database>, credentials: Json<Credentials>) -> Result<HttpResponse, ApiError> {
+    let token = User::login(&db, credentials.into_inner())?;
+    Ok(HttpResponse::Ok().json(token))
+}
+
+#[get("/me")]
+pub async fn get_current_user(db: Data<Database>, user: CurrentUser) -> Result<HttpResponse, ApiError> {
+    let user = User::get_user_by_id(&db, user.id)?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+// not behind the validator, you can't have a token before logging in
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/auth")
+            .wrap(RateLimit)
+            .service(register)
+            .service(login)
+            .service(get_current_user)
+    );
+}
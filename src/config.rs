@@ -0,0 +1,564 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use actix_web::http::header::HeaderName;
+use actix_web::http::Method;
+use dotenv::dotenv;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+/// Read when CONFIG_FILE isn't set, it's fine for it not to exist
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const LLM_PROVIDERS: [&str; 3] = ["openai", "local", "fixture"];
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Settings of the whole app, from a TOML file (see config.example.toml) with env vars taking
+/// precedence. Loaded and validated once at startup.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub llm: LlmConfig,
+    pub ingredients: IngredientsConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub cors: CorsConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// 0.0.0.0 to be reachable from outside a container, 127.0.0.1 for local only
+    pub host: String,
+    pub port: u16,
+    /// actix workers, one per cpu when not set
+    pub workers: Option<usize>,
+    /// ips of the reverse proxies in front, whose X-Forwarded-For is believed
+    pub trusted_proxies: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig { host: "0.0.0.0".to_string(), port: 8000, workers: None, trusted_proxies: vec![] }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    /// most connections the pool opens
+    pub pool_size: u32,
+    /// connections kept open when idle, pool_size when not set
+    pub min_idle: Option<u32>,
+    /// seconds to wait for a connection before giving up with a 503
+    pub connection_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig { url: String::new(), pool_size: 10, min_idle: None, connection_timeout_secs: 30 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    /// openai, local or fixture
+    pub provider: String,
+    /// the provider's default model when not set
+    pub model: Option<String>,
+    pub max_tokens: i32,
+    pub temperature: f32,
+    /// OpenAI compatible server of the local provider
+    pub base_url: String,
+    pub api_key: Option<String>,
+    /// USD per million tokens, for models without built in prices
+    pub prompt_price: Option<f64>,
+    pub completion_price: Option<f64>,
+    /// generations a key, user or ip may queue a day
+    pub daily_quota: i32,
+    /// threads working off generation jobs
+    pub workers: usize,
+    /// whether /health/ready checks that the provider answers
+    pub health_check: bool,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        LlmConfig {
+            provider: "openai".to_string(),
+            model: None,
+            max_tokens: 512,
+            temperature: 0.6,
+            base_url: "http://localhost:11434/v1/".to_string(),
+            api_key: None,
+            prompt_price: None,
+            completion_price: None,
+            daily_quota: 20,
+            workers: 1,
+            health_check: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngredientsConfig {
+    /// trigram similarity (0.0 - 1.0) a name needs to be taken for a known ingredient
+    pub similarity_threshold: f64,
+}
+
+impl Default for IngredientsConfig {
+    fn default() -> Self {
+        IngredientsConfig { similarity_threshold: 0.5 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// signs login tokens, logging in fails without one
+    pub jwt_secret: Option<String>,
+    /// let GET requests through without a key
+    pub public_reads: bool,
+    /// let anyone create an account, otherwise it takes an API key with the write scope
+    pub open_registration: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub per_minute: f64,
+    /// requests allowed at once, per_minute when not set
+    pub burst: Option<f64>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig { per_minute: 120.0, burst: None }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// origins like https://bartender.example.com (no path or trailing slash), or "*". CORS is
+    /// off without any.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// response headers browsers let scripts read
+    pub expose_headers: Vec<String>,
+    /// whether browsers send cookies and Authorization headers along
+    pub allow_credentials: bool,
+    /// seconds browsers may cache the answer to a preflight request
+    pub max_age_secs: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        let strings = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        CorsConfig {
+            allowed_origins: vec![],
+            allowed_methods: strings(&["GET", "POST", "PUT", "PATCH", "DELETE"]),
+            allowed_headers: strings(&["Authorization", "Content-Type", "X-Request-Id"]),
+            expose_headers: strings(&["X-Request-Id", "Retry-After"]),
+            allow_credentials: false,
+            max_age_secs: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err("expected text or json".to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Jaeger collector traces are sent to, e.g. http://localhost:14268/api/traces, no tracing when not set
+    pub jaeger_endpoint: Option<String>,
+    pub service_name: String,
+    pub log_format: LogFormat,
+    /// which logs are written, in the RUST_LOG syntax
+    pub log_filter: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            jaeger_endpoint: None,
+            service_name: "bartender".to_string(),
+            log_format: LogFormat::Text,
+            log_filter: "info".to_string(),
+        }
+    }
+}
+
+/// Everything wrong with the configuration, so that it can be fixed in one go
+#[derive(Debug, PartialEq)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// The config loaded at startup, or the defaults where nothing was loaded (tests)
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Makes `config` the one `get` returns, only the first call counts
+pub fn init(config: Config) {
+    if CONFIG.set(config).is_err() {
+        tracing::warn!("the configuration was already set");
+    }
+}
+
+impl Config {
+    /// Reads CONFIG_FILE (config.toml by default), applies the env overrides (and .env) and
+    /// validates the result
+    pub fn load() -> Result<Config, ConfigError> {
+        dotenv().ok();
+
+        let mut config = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Config::from_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => Config::from_file(DEFAULT_CONFIG_FILE)?,
+            Err(_) => Config::default(),
+        };
+
+        let mut problems = config.apply_env(|key| std::env::var(key).ok());
+        problems.extend(config.validate());
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(problems))
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError(vec![format!("Could not read {}: {}", path, err)]))?;
+        Config::from_toml(&text)
+            .map_err(|ConfigError(problems)| ConfigError(problems.into_iter().map(|x| format!("{}: {}", path, x)).collect()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Config, ConfigError> {
+        toml::from_str(text).map_err(|err| ConfigError(vec![err.to_string().trim().to_string()]))
+    }
+
+    /// Overrides settings with the env vars `var` returns, returning the ones that couldn't be
+    /// parsed
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut env = EnvOverrides { var: &var, problems: vec![] };
+
+        env.set("HOST", &mut self.server.host);
+        env.set("PORT", &mut self.server.port);
+        env.set_list("TRUSTED_PROXIES", &mut self.server.trusted_proxies);
+        env.set_option("WORKERS", &mut self.server.workers);
+
+        env.set("DATABASE_URL", &mut self.database.url);
+        env.set("DATABASE_POOL_SIZE", &mut self.database.pool_size);
+        env.set_option("DATABASE_MIN_IDLE", &mut self.database.min_idle);
+        env.set("DATABASE_CONNECTION_TIMEOUT", &mut self.database.connection_timeout_secs);
+
+        env.set("LLM_PROVIDER", &mut self.llm.provider);
+        env.set_option("LLM_MODEL", &mut self.llm.model);
+        env.set("LLM_MAX_TOKENS", &mut self.llm.max_tokens);
+        env.set("LLM_TEMPERATURE", &mut self.llm.temperature);
+        env.set("LLM_BASE_URL", &mut self.llm.base_url);
+        // each provider has always had its own variable for the key
+        if self.llm.provider == "openai" {
+            env.set_option("OPENAI_API_KEY", &mut self.llm.api_key);
+        } else {
+            env.set_option("LLM_API_KEY", &mut self.llm.api_key);
+        }
+        env.set_option("LLM_PROMPT_PRICE", &mut self.llm.prompt_price);
+        env.set_option("LLM_COMPLETION_PRICE", &mut self.llm.completion_price);
+        env.set("DAILY_LLM_QUOTA", &mut self.llm.daily_quota);
+        env.set("LLM_WORKERS", &mut self.llm.workers);
+        env.set("LLM_HEALTH_CHECK", &mut self.llm.health_check);
+
+        env.set("INGREDIENT_SIMILARITY_THRESHOLD", &mut self.ingredients.similarity_threshold);
+
+        env.set_option("JWT_SECRET", &mut self.auth.jwt_secret);
+        if let Some(value) = var("PUBLIC_READS") {
+            self.auth.public_reads = value == "true" || value == "1";
+        }
+        if let Some(value) = var("OPEN_REGISTRATION") {
+            self.auth.open_registration = value == "true" || value == "1";
+        }
+
+        env.set("RATE_LIMIT_PER_MINUTE", &mut self.rate_limit.per_minute);
+        env.set_option("RATE_LIMIT_BURST", &mut self.rate_limit.burst);
+
+        env.set_list("CORS_ALLOWED_ORIGINS", &mut self.cors.allowed_origins);
+        env.set_list("CORS_ALLOWED_METHODS", &mut self.cors.allowed_methods);
+        env.set_list("CORS_ALLOWED_HEADERS", &mut self.cors.allowed_headers);
+        env.set_list("CORS_EXPOSE_HEADERS", &mut self.cors.expose_headers);
+        env.set("CORS_ALLOW_CREDENTIALS", &mut self.cors.allow_credentials);
+        env.set("CORS_MAX_AGE", &mut self.cors.max_age_secs);
+
+        env.set_option("OTEL_EXPORTER_JAEGER_ENDPOINT", &mut self.telemetry.jaeger_endpoint);
+        env.set("OTEL_SERVICE_NAME", &mut self.telemetry.service_name);
+        env.set("LOG_FORMAT", &mut self.telemetry.log_format);
+        env.set("RUST_LOG", &mut self.telemetry.log_filter);
+
+        env.problems
+    }
+
+    /// What is wrong with the settings, empty when they are fine
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = vec![];
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        check(!self.server.host.trim().is_empty(), "server.host (HOST) must not be empty");
+        check(self.server.workers != Some(0), "server.workers (WORKERS) must be at least 1");
+        for proxy in &self.server.trusted_proxies {
+            check(
+                proxy.parse::<IpAddr>().is_ok(),
+                &format!("server.trusted_proxies (TRUSTED_PROXIES): {:?} is not an ip", proxy),
+            );
+        }
+
+        check(!self.database.url.is_empty(), "database.url (DATABASE_URL) is required");
+        check(self.database.pool_size > 0, "database.pool_size (DATABASE_POOL_SIZE) must be at least 1");
+        check(
+            self.database.min_idle.map(|x| x <= self.database.pool_size).unwrap_or(true),
+            "database.min_idle (DATABASE_MIN_IDLE) must not be more than database.pool_size",
+        );
+        check(
+            self.database.connection_timeout_secs > 0,
+            "database.connection_timeout_secs (DATABASE_CONNECTION_TIMEOUT) must be at least 1",
+        );
+
+        check(
+            LLM_PROVIDERS.contains(&self.llm.provider.as_str()),
+            &format!("llm.provider (LLM_PROVIDER) is {:?}, expected openai, local or fixture", self.llm.provider),
+        );
+        check(
+            self.llm.provider != "openai" || self.llm.api_key.as_ref().map(|x| !x.is_empty()).unwrap_or(false),
+            "llm.api_key (OPENAI_API_KEY) is required by the openai provider, use LLM_PROVIDER=fixture to do without",
+        );
+        check(
+            self.llm.provider != "local" || is_http_url(&self.llm.base_url),
+            "llm.base_url (LLM_BASE_URL) must be an http:// or https:// url",
+        );
+        check(self.llm.max_tokens > 0, "llm.max_tokens (LLM_MAX_TOKENS) must be at least 1");
+        check((0.0..=2.0).contains(&self.llm.temperature), "llm.temperature (LLM_TEMPERATURE) must be between 0 and 2");
+        check(
+            self.llm.prompt_price.is_some() == self.llm.completion_price.is_some(),
+            "llm.prompt_price and llm.completion_price (LLM_PROMPT_PRICE, LLM_COMPLETION_PRICE) go together",
+        );
+        check(
+            self.llm.prompt_price.unwrap_or(0.0) >= 0.0 && self.llm.completion_price.unwrap_or(0.0) >= 0.0,
+            "llm prices must not be negative",
+        );
+        check(self.llm.daily_quota >= 0, "llm.daily_quota (DAILY_LLM_QUOTA) must not be negative");
+        check(self.llm.workers > 0, "llm.workers (LLM_WORKERS) must be at least 1");
+
+        check(
+            (0.0..=1.0).contains(&self.ingredients.similarity_threshold),
+            "ingredients.similarity_threshold (INGREDIENT_SIMILARITY_THRESHOLD) must be between 0 and 1",
+        );
+
+        check(
+            self.auth.jwt_secret.as_ref().map(|x| !x.is_empty()).unwrap_or(true),
+            "auth.jwt_secret (JWT_SECRET) must not be empty, leave it out to disable logging in",
+        );
+
+        check(self.rate_limit.per_minute > 0.0, "rate_limit.per_minute (RATE_LIMIT_PER_MINUTE) must be more than 0");
+        check(
+            self.rate_limit.burst.map(|x| x >= 1.0).unwrap_or(true),
+            "rate_limit.burst (RATE_LIMIT_BURST) must be at least 1",
+        );
+
+        for origin in &self.cors.allowed_origins {
+            check(
+                origin == "*" || is_origin(origin),
+                &format!("cors.allowed_origins (CORS_ALLOWED_ORIGINS): {:?} is not an origin like https://example.com", origin),
+            );
+        }
+        check(
+            !(self.cors.allow_credentials && self.cors.allowed_origins.iter().any(|x| x == "*")),
+            "cors.allow_credentials (CORS_ALLOW_CREDENTIALS) needs the origins listed, browsers refuse it with \"*\"",
+        );
+        for method in &self.cors.allowed_methods {
+            check(
+                Method::from_bytes(method.as_bytes()).is_ok(),
+                &format!("cors.allowed_methods (CORS_ALLOWED_METHODS): {:?} is not a method", method),
+            );
+        }
+        for name in self.cors.allowed_headers.iter().chain(&self.cors.expose_headers) {
+            check(
+                HeaderName::from_bytes(name.as_bytes()).is_ok(),
+                &format!("cors: {:?} is not a header name", name),
+            );
+        }
+
+        check(
+            self.telemetry.jaeger_endpoint.as_ref().map(|x| is_http_url(x)).unwrap_or(true),
+            "telemetry.jaeger_endpoint (OTEL_EXPORTER_JAEGER_ENDPOINT) must be an http:// or https:// url",
+        );
+        if let Err(err) = EnvFilter::try_new(&self.telemetry.log_filter) {
+            check(false, &format!("telemetry.log_filter (RUST_LOG) is invalid: {}", err));
+        }
+
+        problems
+    }
+}
+
+/// Parses env vars into settings, remembering the ones that didn't parse
+struct EnvOverrides<'a, F: Fn(&str) -> Option<String>> {
+    var: &'a F,
+    problems: Vec<String>,
+}
+
+impl<F: Fn(&str) -> Option<String>> EnvOverrides<'_, F> {
+    fn parse<T: FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        let value = (self.var)(key)?;
+        match value.trim().parse::<T>() {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                self.problems.push(format!("{}={:?} is invalid: {}", key, value, err));
+                None
+            },
+        }
+    }
+
+    fn set<T: FromStr>(&mut self, key: &str, setting: &mut T)
+    where
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = self.parse(key) {
+            *setting = value;
+        }
+    }
+
+    /// A comma separated list
+    fn set_list(&mut self, key: &str, setting: &mut Vec<String>) {
+        if let Some(value) = (self.var)(key) {
+            *setting = value.split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect();
+        }
+    }
+
+    /// An empty variable unsets the setting
+    fn set_option<T: FromStr>(&mut self, key: &str, setting: &mut Option<T>)
+    where
+        T::Err: fmt::Display,
+    {
+        match (self.var)(key) {
+            Some(value) if value.trim().is_empty() => *setting = None,
+            Some(_) => {
+                if let Some(value) = self.parse(key) {
+                    *setting = Some(value);
+                }
+            },
+            None => (),
+        }
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    ["http://", "https://"].iter()
+        .any(|scheme| url.strip_prefix(scheme).map(|rest| !rest.is_empty()).unwrap_or(false))
+}
+
+/// scheme://host[:port], what browsers send as Origin
+fn is_origin(origin: &str) -> bool {
+    is_http_url(origin) && origin.split("://").nth(1).map(|x| !x.contains('/')).unwrap_or(false)
+}
+
+
+#[test]
+pub fn test_config() {
+    let config = Config::from_toml(r#"
+        [server]
+        port = 9000
+
+        [database]
+        url = "postgres://localhost/bartender"
+        pool_size = 4
+
+        [llm]
+        provider = "fixture"
+
+        [cors]
+        allowed_origins = ["http://localhost:3000"]
+    "#).unwrap();
+    assert_eq!(config.server.port, 9000);
+    assert_eq!(config.server.host, "0.0.0.0");
+    assert_eq!(config.database.pool_size, 4);
+    assert_eq!(config.llm.max_tokens, 512);
+    assert_eq!(config.validate(), Vec::<String>::new());
+
+    assert!(Config::from_toml("[server]\nprot = 9000").is_err());
+    assert!(Config::from_toml("[server]\nport = \"high\"").is_err());
+
+    let mut overridden = config.clone();
+    let problems = overridden.apply_env(|key| match key {
+        "PORT" => Some("8080".to_string()),
+        "LLM_MODEL" => Some("llama3".to_string()),
+        "RATE_LIMIT_BURST" => Some("lots".to_string()),
+        "LOG_FORMAT" => Some("json".to_string()),
+        "CORS_ALLOWED_ORIGINS" => Some("https://a.example.com, https://b.example.com/".to_string()),
+        _ => None,
+    });
+    assert_eq!(overridden.server.port, 8080);
+    assert_eq!(overridden.llm.model.as_deref(), Some("llama3"));
+    assert_eq!(overridden.telemetry.log_format, LogFormat::Json);
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].starts_with("RATE_LIMIT_BURST"));
+    // the trailing slash doesn't match what browsers send
+    assert_eq!(overridden.validate().len(), 1);
+
+    let mut cors = config.clone();
+    cors.cors.allowed_origins = vec!["*".to_owned()];
+    cors.cors.allow_credentials = true;
+    cors.cors.allowed_methods.push("GET POST".to_owned());
+    assert_eq!(cors.validate().len(), 2);
+
+    let invalid = Config::default();
+    let problems = invalid.validate();
+    assert!(problems.iter().any(|x| x.contains("DATABASE_URL")));
+    assert!(problems.iter().any(|x| x.contains("OPENAI_API_KEY")));
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+
+use actix_web::{http::{header, StatusCode}, HttpResponse, ResponseError};
+use diesel::r2d2::PoolError;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use crate::Response;
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The requested row does not exist
+    NotFound(String),
+    /// The request conflicts with an existing row (unique constraints)
+    Conflict(String),
+    /// The request references rows that don't exist or is otherwise invalid
+    BadRequest(String),
+    /// No valid API key was given
+    Unauthorized(String),
+    /// The API key doesn't have the scope the request needs
+    Forbidden(String),
+    /// Too many requests or generations, the number is the seconds until trying again makes sense
+    TooManyRequests(String, u64),
+    /// No database connection could be checked out of the pool
+    ServiceUnavailable(String),
+    /// The LLM provider failed or returned something unusable
+    Llm(String),
+    /// Anything else that went wrong talking to the database
+    Internal(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::BadRequest(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::TooManyRequests(msg, _)
+            | ApiError::ServiceUnavailable(msg)
+            | ApiError::Llm(msg)
+            | ApiError::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::TooManyRequests(_, _) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Llm(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let response = Response {
+            message: self.to_string(),
+        };
+        let mut builder = HttpResponse::build(self.status_code());
+        match self {
+            ApiError::Unauthorized(_) => {
+                builder.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+            },
+            ApiError::TooManyRequests(_, retry_after) => {
+                builder.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+            },
+            _ => (),
+        }
+        builder.json(response)
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => ApiError::NotFound("Resource not found".to_string()),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                match info.constraint_name() {
+                    Some("unique_name") | Some("unique_name_normalized") => {
+                        ApiError::Conflict("A cocktail with that name already exists".to_string())
+                    }
+                    Some("unique_username") => ApiError::Conflict("That username is taken".to_string()),
+                    _ => ApiError::Conflict(info.message().to_string()),
+                }
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                ApiError::BadRequest(info.message().to_string())
+            }
+            err => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<PoolError> for ApiError {
+    fn from(err: PoolError) -> Self {
+        ApiError::ServiceUnavailable(format!("Database unavailable: {}", err))
+    }
+}
+
+impl From<openai_api_rust::Error> for ApiError {
+    fn from(err: openai_api_rust::Error) -> Self {
+        ApiError::Llm(err.to_string())
+    }
+}
+
+
+#[test]
+pub fn test_api_error_status_codes() {
+    assert_eq!(ApiError::from(DieselError::NotFound).status_code(), StatusCode::NOT_FOUND);
+    assert_eq!(ApiError::from(DieselError::RollbackTransaction).status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        ApiError::from(openai_api_rust::Error::RequestError("timeout".to_string())).status_code(),
+        StatusCode::BAD_GATEWAY
+    );
+    assert_eq!(ApiError::ServiceUnavailable("".to_string()).status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(ApiError::Unauthorized("".to_string()).status_code(), StatusCode::UNAUTHORIZED);
+    assert_eq!(ApiError::Forbidden("".to_string()).status_code(), StatusCode::FORBIDDEN);
+
+    let limited = ApiError::TooManyRequests("".to_string(), 30).error_response();
+    assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(limited.headers().get(header::RETRY_AFTER).unwrap(), "30");
+}
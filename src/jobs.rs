@@ -0,0 +1,34 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tracing::error;
+use crate::llm::CocktailSuggester;
+use crate::models::generation_jobs::GenerationJob;
+use crate::repository::database::Database;
+
+/// How long an idle worker waits before looking for new jobs
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Starts `workers` threads that work off queued generation jobs. LLM calls and inserts are
+/// blocking so they are kept off the actix workers.
+pub fn spawn_generation_workers(db: Database, suggester: Arc<dyn CocktailSuggester>, workers: usize) {
+    for n in 0..workers {
+        let db = db.clone();
+        let suggester = suggester.clone();
+
+        thread::Builder::new()
+            .name(format!("generation-worker-{}", n))
+            .spawn(move || loop {
+                match GenerationJob::run_next_job(&db, suggester.as_ref()) {
+                    Ok(true) => (),
+                    Ok(false) => thread::sleep(POLL_INTERVAL),
+                    Err(err) => {
+                        error!(worker = n, error = %err, "generation worker failed");
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+            })
+            .expect("Failed to spawn generation worker");
+    }
+}
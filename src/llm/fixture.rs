@@ -0,0 +1,48 @@
+use crate::error::ApiError;
+
+use super::{CocktailSuggester, Completion};
+
+const FIXTURE_RESPONSE: &str = "[[\"Whiskey Sour\",[[\"Whiskey\",\"2 ounces\"],[\"Lemon juice\",\"1 ounce\"],[\"Simple syrup\",\"1/2 ounce\"]],[\"Add whiskey, lemon juice and simple syrup to a shaker with ice.\",\"Shake and strain into a rocks glass with fresh ice.\",\"Garnish with a lemon wedge.\"]],[\"Tom Collins\",[[\"Gin\",\"2 ounces\"],[\"Lemon juice\",\"1 ounce\"],[\"Simple syrup\",\"1/2 ounce\"],[\"Club soda\",\"3 ounces\"]],[\"Fill a shaker with ice cubes.\",\"Add gin, lemon juice and simple syrup to the shaker.\",\"Shake and strain into a highball glass filled with ice.\",\"Top with club soda.\",\"Garnish with a lemon slice.\"]]]";
+
+/// Answers every prompt with the same canned response. Used for tests and local
+/// development without a model.
+pub struct FixtureSuggester {
+    response: String,
+}
+
+impl FixtureSuggester {
+    pub fn new(response: &str) -> Self {
+        FixtureSuggester { response: response.to_string() }
+    }
+}
+
+impl Default for FixtureSuggester {
+    fn default() -> Self {
+        FixtureSuggester::new(FIXTURE_RESPONSE)
+    }
+}
+
+impl CocktailSuggester for FixtureSuggester {
+    fn provider(&self) -> &str {
+        "fixture"
+    }
+
+    fn model(&self) -> &str {
+        "fixture"
+    }
+
+    fn complete(&self, _prompt: &str) -> Result<Completion, ApiError> {
+        Ok(Completion { text: self.response.clone(), usage: None })
+    }
+}
+
+
+#[test]
+pub fn test_fixture_suggester() {
+    let suggester = FixtureSuggester::default();
+    let result = suggester.suggest_cocktails(&["Gin".to_owned()]).unwrap();
+
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].name, "Whiskey Sour");
+    assert_eq!(result[1].ingredients[3].name, "Club soda");
+}
@@ -0,0 +1,60 @@
+use openai_api_rust::{Auth, OpenAI};
+
+use crate::config::LlmConfig;
+use crate::error::ApiError;
+
+use super::openai::{chat_completion, list_models};
+use super::{ChatSettings, CocktailSuggester, Completion};
+
+/// Suggests cocktails using a self hosted model behind an OpenAI compatible API
+/// (llama.cpp server, Ollama, vLLM, ...)
+pub struct LocalChatSuggester {
+    base_url: String,
+    api_key: String,
+    settings: ChatSettings,
+}
+
+impl LocalChatSuggester {
+    pub fn new(base_url: &str, api_key: Option<String>, settings: ChatSettings) -> Self {
+        // the client appends paths like "chat/completions" straight onto the base url
+        let base_url = if base_url.ends_with('/') {
+            base_url.to_string()
+        } else {
+            format!("{}/", base_url)
+        };
+
+        LocalChatSuggester {
+            base_url,
+            // most local servers ignore the key but the header is always sent
+            api_key: api_key.unwrap_or_default(),
+            settings,
+        }
+    }
+
+    pub fn from_config(config: &LlmConfig) -> Self {
+        LocalChatSuggester::new(
+            &config.base_url,
+            config.api_key.clone(),
+            ChatSettings::from_config(config, "llama3"),
+        )
+    }
+}
+
+impl CocktailSuggester for LocalChatSuggester {
+    fn provider(&self) -> &str {
+        "local"
+    }
+
+    fn model(&self) -> &str {
+        &self.settings.model
+    }
+
+    fn complete(&self, prompt: &str) -> Result<Completion, ApiError> {
+        let openai = OpenAI::new(Auth::new(&self.api_key), &self.base_url);
+        chat_completion(&openai, &self.settings, prompt)
+    }
+
+    fn ping(&self) -> Result<(), ApiError> {
+        list_models(&self.base_url, &self.api_key)
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use tracing::{debug, warn};
+
+use crate::config::LlmConfig;
+use crate::error::ApiError;
+use crate::models::cocktails::CocktailData;
+use crate::telemetry::llm_metrics;
+
+use self::fixture::FixtureSuggester;
+use self::local::LocalChatSuggester;
+use self::openai::OpenAiChatSuggester;
+
+pub mod fixture;
+pub mod local;
+pub mod openai;
+pub mod parse;
+pub mod pricing;
+
+/// Tokens a completion used, as reported by the provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// What a model answered with
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    /// None when the provider doesn't say
+    pub usage: Option<TokenUsage>,
+}
+
+/// Something that can come up with new cocktails for a list of ingredients
+pub trait CocktailSuggester: Send + Sync {
+    /// Name of the provider, used in logs
+    fn provider(&self) -> &str;
+
+    /// Model the completions are requested from
+    fn model(&self) -> &str;
+
+    /// Sends `prompt` to the model and returns the raw text it answered with
+    fn complete(&self, prompt: &str) -> Result<Completion, ApiError>;
+
+    /// Checks that the provider can be reached without generating anything, for readiness
+    fn ping(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    fn suggest_cocktails(&self, ingredients: &[String]) -> Result<Vec<CocktailData>, ApiError> {
+        let prompt = cocktail_prompt(ingredients);
+        // prompts and responses are long, only logged with RUST_LOG=bartender=debug
+        debug!(provider = self.provider(), model = self.model(), prompt = %prompt, "asking for cocktails");
+
+        let started = Instant::now();
+        let completion = self.complete(&prompt);
+        llm_metrics().record_completion(
+            self.provider(),
+            self.model(),
+            started.elapsed(),
+            completion.is_ok(),
+            completion.as_ref().ok().and_then(|x| x.usage.as_ref()),
+        );
+        let message = completion?.text;
+
+        debug!(provider = self.provider(), model = self.model(), response = %message, "model answered");
+        CocktailData::parse_message(&message).map_err(|err| {
+            warn!(provider = self.provider(), model = self.model(), reason = %err.reason, "could not parse the model response");
+            debug!(raw = %err.raw, "unparsable model response");
+            llm_metrics().record_parse_failure(self.provider(), self.model());
+            ApiError::from(err)
+        })
+    }
+}
+
+/// Settings shared by the chat completion providers
+#[derive(Debug, Clone)]
+pub struct ChatSettings {
+    pub model: String,
+    pub max_tokens: i32,
+    pub temperature: f32,
+}
+
+impl ChatSettings {
+    pub fn from_config(config: &LlmConfig, default_model: &str) -> Self {
+        ChatSettings {
+            model: config.model.clone().unwrap_or_else(|| default_model.to_string()),
+            max_tokens: config.max_tokens,
+            temperature: config.temperature,
+        }
+    }
+}
+
+pub fn cocktail_prompt(ingredients: &[String]) -> String {
+    let ingredients_str = ingredients.join(",");
+    // let proompt = format!("What cockatils can I make with these ingredients? [{},ice] Format the result as a json array like this [[name,[ingredents list],[instructions list]],...]. Where 'name' is the cocktail name string, 'ingredients list' is an array of ingredient name and measurement tuples, and instructions is an array of instruction strings. Limit to 2 cocktails.", ingredients_str);
+    // let proompt = format!("What cockatils can I make with these ingredients? [{},ice] Format the response as a JSON array of tuples, where each tuple has the format [name, [[ingredient_name, amount], ...], [instruction, ...]].  Limit to 2 cocktails.", ingredients_str);
+    format!("What cockatils can I make with these ingredients? [{},ice] Format the response as a JSON array of tuples, where each tuple has the format [name_string, [[ingredient_name_string, amount_string], ...], [instruction_string, ...]]. Where 'name' is the cocktail name string, 'ingredients list' is an array of ingredient name and measurement tuples, and instructions is an array of instruction strings. Limit to 2 cocktails.", ingredients_str)
+}
+
+/// Builds the suggester selected by `llm.provider`: `openai` (default), `local` or `fixture`
+pub fn suggester_from_config(config: &LlmConfig) -> Arc<dyn CocktailSuggester> {
+    match config.provider.as_str() {
+        "openai" => Arc::new(OpenAiChatSuggester::from_config(config)),
+        "local" => Arc::new(LocalChatSuggester::from_config(config)),
+        "fixture" => Arc::new(FixtureSuggester::default()),
+        other => panic!("Unknown LLM_PROVIDER {}, expected openai, local or fixture", other),
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use openai_api_rust::chat::{ChatApi, ChatBody};
+use openai_api_rust::{Auth, Message, OpenAI, Role};
+
+use crate::config::LlmConfig;
+use crate::error::ApiError;
+
+use super::{ChatSettings, CocktailSuggester, Completion, TokenUsage};
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1/";
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Suggests cocktails using OpenAI's chat completions API
+pub struct OpenAiChatSuggester {
+    api_key: Option<String>,
+    settings: ChatSettings,
+}
+
+impl OpenAiChatSuggester {
+    pub fn new(api_key: Option<String>, settings: ChatSettings) -> Self {
+        OpenAiChatSuggester { api_key, settings }
+    }
+
+    pub fn from_config(config: &LlmConfig) -> Self {
+        OpenAiChatSuggester::new(
+            config.api_key.clone(),
+            ChatSettings::from_config(config, "gpt-4o-mini"),
+        )
+    }
+}
+
+impl CocktailSuggester for OpenAiChatSuggester {
+    fn provider(&self) -> &str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.settings.model
+    }
+
+    fn complete(&self, prompt: &str) -> Result<Completion, ApiError> {
+        let api_key = self.api_key.as_ref()
+            .ok_or_else(|| ApiError::Llm("Missing OPENAI_API_KEY".to_string()))?;
+        let openai = OpenAI::new(Auth::new(api_key), OPENAI_BASE_URL);
+        chat_completion(&openai, &self.settings, prompt)
+    }
+
+    fn ping(&self) -> Result<(), ApiError> {
+        let api_key = self.api_key.as_ref()
+            .ok_or_else(|| ApiError::Llm("Missing OPENAI_API_KEY".to_string()))?;
+        list_models(OPENAI_BASE_URL, api_key)
+    }
+}
+
+/// Lists the models of an OpenAI compatible API, which is free and quick, to see that it answers
+pub fn list_models(base_url: &str, api_key: &str) -> Result<(), ApiError> {
+    reqwest::blocking::Client::new()
+        .get(format!("{}/models", base_url.trim_end_matches('/')))
+        .timeout(PING_TIMEOUT)
+        .bearer_auth(api_key)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| ApiError::Llm(format!("Could not list the models: {}", err)))?;
+    Ok(())
+}
+
+/// Sends `prompt` as a single user message to an OpenAI compatible chat completions endpoint
+pub fn chat_completion(openai: &OpenAI, settings: &ChatSettings, prompt: &str) -> Result<Completion, ApiError> {
+    let body = ChatBody {
+        model: settings.model.clone(),
+        max_tokens: Some(settings.max_tokens),
+        temperature: Some(settings.temperature),
+        n: Some(1),
+        frequency_penalty: None,
+        presence_penalty: None,
+        top_p: Some(1_f32),
+        stream: Some(false),
+        stop: None,
+        logit_bias: None,
+        user: None,
+        messages: vec![
+            Message {
+                role: Role::System,
+                content: "You are a bartender. Only answer with JSON.".to_string(),
+            },
+            Message { role: Role::User, content: prompt.to_string() },
+        ],
+    };
+
+    let rs = openai.chat_completion_create(&body)?;
+    let usage = match (rs.usage.prompt_tokens, rs.usage.completion_tokens) {
+        (Some(prompt_tokens), Some(completion_tokens)) => Some(TokenUsage {
+            prompt_tokens: prompt_tokens as u64,
+            completion_tokens: completion_tokens as u64,
+        }),
+        _ => None,
+    };
+    let message = rs.choices.into_iter()
+        .next()
+        .and_then(|choice| choice.message)
+        .ok_or_else(|| ApiError::Llm(format!("{} returned no completion", settings.model)))?;
+    Ok(Completion { text: message.content, usage })
+}
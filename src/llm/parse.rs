@@ -0,0 +1,282 @@
+use std::fmt;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::error::ApiError;
+use crate::models::cocktails::CocktailData;
+
+// keep in sync with the column sizes in the bartender migrations
+const MAX_NAME_LEN: usize = 100;
+const MAX_MEASUREMENT_LEN: usize = 50;
+const MAX_INSTRUCTION_LEN: usize = 500;
+
+/// A model response that could not be turned into cocktails
+#[derive(Debug)]
+pub struct ParseError {
+    pub reason: String,
+    /// the full text the model answered with
+    pub raw: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not parse cocktails from model response: {}", self.reason)
+    }
+}
+
+impl From<ParseError> for ApiError {
+    fn from(err: ParseError) -> Self {
+        ApiError::Llm(err.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawResponse {
+    List(Vec<RawCocktail>),
+    Wrapped { cocktails: Vec<RawCocktail> },
+    Single(RawCocktail),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCocktail {
+    // [name, [[ingredient, amount], ...], [instruction, ...]]
+    Tuple(String, Vec<RawIngredient>, Vec<String>),
+    // {name, ingredients: [{name, amount}, ...], instructions: [...]}
+    Object {
+        name: String,
+        ingredients: Vec<RawIngredient>,
+        instructions: Vec<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawIngredient {
+    Tuple(String, String),
+    Object {
+        name: String,
+        #[serde(alias = "measurement")]
+        amount: String,
+    },
+}
+
+impl RawIngredient {
+    fn into_tuple(self) -> (String, String) {
+        match self {
+            RawIngredient::Tuple(n, amount) | RawIngredient::Object { name: n, amount } => {
+                (n.trim().to_string(), amount.trim().to_string())
+            }
+        }
+    }
+}
+
+impl RawCocktail {
+    fn into_parts(self) -> (String, Vec<(String, String)>, Vec<String>) {
+        let (n, ingredients, instructions) = match self {
+            RawCocktail::Tuple(n, ingredients, instructions) => (n, ingredients, instructions),
+            RawCocktail::Object { name: n, ingredients, instructions } => (n, ingredients, instructions),
+        };
+
+        (
+            n.trim().to_string(),
+            ingredients.into_iter().map(RawIngredient::into_tuple).collect(),
+            instructions.iter().map(|x| x.trim().to_string()).collect(),
+        )
+    }
+}
+
+/// Parses the cocktails out of a model response. The JSON may be wrapped in prose or code
+/// fences, contain trailing commas and use either the tuple or the object format. Cocktails
+/// that would not fit in the database are skipped.
+pub fn parse_cocktails(message: &str) -> Result<Vec<CocktailData>, ParseError> {
+    let error = |reason: String| ParseError { reason, raw: message.to_string() };
+
+    // prose before the JSON may have brackets too, the first candidate that parses is taken
+    let mut first_error = None;
+    let response = json_candidates(message).find_map(|json| {
+        match serde_json::from_str::<RawResponse>(&strip_trailing_commas(json)) {
+            Ok(response) => Some(response),
+            Err(err) => {
+                first_error.get_or_insert(err.to_string());
+                None
+            },
+        }
+    });
+
+    let raw_cocktails = match response {
+        Some(RawResponse::List(list)) => list,
+        Some(RawResponse::Wrapped { cocktails }) => cocktails,
+        Some(RawResponse::Single(cocktail)) => vec![cocktail],
+        None => return Err(error(first_error.unwrap_or_else(|| "no JSON array or object found".to_string()))),
+    };
+
+    let total = raw_cocktails.len();
+    let parsed: Vec<CocktailData> = raw_cocktails.into_iter()
+        .map(RawCocktail::into_parts)
+        .filter(|parts| match validate(parts) {
+            Ok(()) => true,
+            Err(reason) => {
+                warn!(name = %parts.0, reason = %reason, "skipping suggested cocktail");
+                false
+            }
+        })
+        .map(CocktailData::from)
+        .collect();
+
+    if parsed.is_empty() {
+        return Err(error(format!("none of the {} cocktails were valid", total)));
+    }
+
+    Ok(parsed)
+}
+
+fn validate((n, ingredients, instructions): &(String, Vec<(String, String)>, Vec<String>)) -> Result<(), String> {
+    check_text("name", n, MAX_NAME_LEN)?;
+
+    if ingredients.is_empty() {
+        return Err("no ingredients".to_string());
+    }
+    for (ing_name, amount) in ingredients {
+        check_text("ingredient name", ing_name, MAX_NAME_LEN)?;
+        check_text("measurement", amount, MAX_MEASUREMENT_LEN)?;
+    }
+
+    if instructions.is_empty() {
+        return Err("no instructions".to_string());
+    }
+    for instruction in instructions {
+        check_text("instruction", instruction, MAX_INSTRUCTION_LEN)?;
+    }
+
+    Ok(())
+}
+
+fn check_text(field: &str, value: &str, max_len: usize) -> Result<(), String> {
+    if value.is_empty() {
+        return Err(format!("{} is empty", field));
+    }
+    if value.chars().count() > max_len {
+        return Err(format!("{} is longer than {} characters", field, max_len));
+    }
+    Ok(())
+}
+
+/// The balanced arrays and objects in `message`, starting at each `[` or `{` in turn
+fn json_candidates(message: &str) -> impl Iterator<Item = &str> {
+    message.match_indices(['[', '{'])
+        .filter_map(move |(start, _)| extract_json(message, start))
+}
+
+/// Returns the balanced JSON array or object starting at `start` of `message`
+fn extract_json(message: &str, start: usize) -> Option<&str> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in message[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => depth += 1,
+            ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&message[start..=start + i]);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Removes commas directly before a closing bracket, outside of strings
+fn strip_trailing_commas(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in json.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ']' || c == '}' {
+            let trimmed_len = out.trim_end().len();
+            if out[..trimmed_len].ends_with(',') {
+                out.truncate(trimmed_len - 1);
+            }
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+
+#[test]
+pub fn test_parse_cocktails_from_prose() {
+    let message = "Sure! Here are some cocktails:\n```json\n[[\"Gimlet\",[[\"Gin\",\"2 ounces\"],[\"Lime juice\",\"1 ounce\"],],[\"Shake with ice.\",\"Strain into a coupe.\"],],]\n```\nEnjoy [responsibly]!";
+    let result = parse_cocktails(message).unwrap();
+
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].name, "Gimlet");
+    assert_eq!(result[0].ingredients[1].measurement, "1 ounce");
+    assert_eq!(result[0].instructions[1].step, 1);
+}
+
+#[test]
+pub fn test_parse_cocktails_after_brackets_in_prose() {
+    let message = "[Note] here you go: [[\"Gimlet\",[[\"Gin\",\"2 ounces\"]],[\"Shake with ice.\"]]]";
+    let result = parse_cocktails(message).unwrap();
+
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].name, "Gimlet");
+}
+
+#[test]
+pub fn test_parse_cocktails_object_format() {
+    let message = "{\"cocktails\": [{\"name\": \"Daiquiri\", \"ingredients\": [{\"name\": \"Rum\", \"amount\": \"2 oz\"}, [\"Lime juice\", \"1 oz\"]], \"instructions\": [\"Shake, then strain.\"]}]}";
+    let result = parse_cocktails(message).unwrap();
+
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].name, "Daiquiri");
+    assert_eq!(result[0].ingredients[0].name, "Rum");
+    assert_eq!(result[0].ingredients[1].name, "Lime juice");
+}
+
+#[test]
+pub fn test_parse_cocktails_skips_invalid() {
+    let long_name = "x".repeat(MAX_NAME_LEN + 1);
+    let message = format!("[[\"{}\",[[\"Gin\",\"2 oz\"]],[\"Stir\"]],[\"Martini\",[[\"Gin\",\"2 oz\"]],[\"Stir\"]],[\"Empty\",[],[\"Stir\"]]]", long_name);
+    let result = parse_cocktails(&message).unwrap();
+
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].name, "Martini");
+}
+
+#[test]
+pub fn test_parse_cocktails_error_keeps_raw() {
+    let message = "I'm sorry, I can't help with that.";
+    let err = parse_cocktails(message).unwrap_err();
+
+    assert_eq!(err.raw, message);
+}
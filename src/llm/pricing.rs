@@ -0,0 +1,56 @@
+use crate::config;
+
+use super::TokenUsage;
+
+/// USD per million prompt and completion tokens of the OpenAI models, checked June 2024
+const OPENAI_PRICES: [(&str, f64, f64); 6] = [
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 5.00, 15.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-4", 30.00, 60.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("gpt-3.5-turbo-instruct", 1.50, 2.00),
+];
+
+/// USD per million prompt and completion tokens. `llm.prompt_price` and `llm.completion_price`
+/// override the built in prices, self hosted and fixture models are free.
+pub fn price(provider: &str, model: &str) -> Option<(f64, f64)> {
+    let llm = &config::get().llm;
+    if let (Some(prompt), Some(completion)) = (llm.prompt_price, llm.completion_price) {
+        return Some((prompt, completion));
+    }
+    default_price(provider, model)
+}
+
+fn default_price(provider: &str, model: &str) -> Option<(f64, f64)> {
+    match provider {
+        "openai" => OPENAI_PRICES.iter()
+            // the longest name that prefixes the model, so dated snapshots are priced too
+            .filter(|(name, _, _)| model == *name || model.starts_with(&format!("{}-", name)))
+            .max_by_key(|(name, _, _)| name.len())
+            .map(|(_, prompt, completion)| (*prompt, *completion)),
+        "local" | "fixture" => Some((0.0, 0.0)),
+        _ => None,
+    }
+}
+
+/// What `usage` cost in USD, if the model's price is known
+pub fn estimate_cost(provider: &str, model: &str, usage: &TokenUsage) -> Option<f64> {
+    price(provider, model).map(|(prompt, completion)| {
+        (usage.prompt_tokens as f64 * prompt + usage.completion_tokens as f64 * completion) / 1_000_000.0
+    })
+}
+
+
+#[test]
+pub fn test_default_price() {
+    assert_eq!(default_price("openai", "gpt-4o"), Some((5.0, 15.0)));
+    assert_eq!(default_price("openai", "gpt-4o-mini-2024-07-18"), Some((0.15, 0.6)));
+    assert_eq!(default_price("openai", "gpt-3.5-turbo-instruct"), Some((1.5, 2.0)));
+    assert_eq!(default_price("openai", "gpt-4oo"), None);
+    assert_eq!(default_price("local", "llama3"), Some((0.0, 0.0)));
+
+    let usage = TokenUsage { prompt_tokens: 1000, completion_tokens: 500 };
+    assert_eq!(estimate_cost("openai", "gpt-4o", &usage), Some(0.0125));
+    assert_eq!(estimate_cost("other", "gpt-4o", &usage), None);
+}
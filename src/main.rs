@@ -1,50 +1,25 @@
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder, Result};
-// use actix_web_opentelemetry::RequestTracing;
-// use opentelemetry::{global, Context};
+use actix_web::middleware::Condition;
+use actix_web::{web, App, HttpResponse, HttpServer, Result};
+use actix_web_opentelemetry::RequestTracing;
 use serde::Serialize;
-
-// use crate::repository::database::Database;
-// use crate::models::todo::Todo;
+use tracing::info;
 
 mod api;
+mod config;
+mod error;
+mod jobs;
+mod llm;
 mod models;
 mod repository;
-// mod telemetry;
+// the DieselFilter derive looks tables up in crate::schema
+use repository::schema;
+mod telemetry;
 
 #[derive(Serialize)]
 pub struct Response {
     pub message: String,
 }
 
-#[get("/health")]
-async fn healthcheck() -> impl Responder {
-    let response = Response {
-        message: "Everything is working fine".to_string(),
-    };
-    HttpResponse::Ok().json(response)
-}
-/*
-#[get("/metrics")]
-async fn metrics(
-    telemetry: web::Data<telemetry::OpenTelemetryStack>,
-    db: web::Data<Database>, 
-    request: HttpRequest,
-) -> impl Responder {
-    let categories = Todo::get_categories(&db);
-    let todos = Todo::get_todos(&db);
-
-    let meter = global::meter("global");
-    let todo_count = meter.i64_observable_gauge("todo_count").with_description("Number of todos").init();
-    let category_count = meter.i64_observable_gauge("category count").with_description("Number of categories").init();
-
-    let cx = Context::current();
-
-    todo_count.observe(&cx, todos.len() as i64, &[]);
-    category_count.observe(&cx, categories.len() as i64, &[]);
-    telemetry.metrics_handler().call(request).await
-}
-*/
- 
 async fn not_found() -> Result<HttpResponse> {
     let response = Response {
         message: "Resource not found".to_string(),
@@ -52,35 +27,104 @@ async fn not_found() -> Result<HttpResponse> {
     Ok(HttpResponse::NotFound().json(response))
 }
 
+/// `cargo run -- create-api-key <name> <read,write,generate>` prints a new key,
+/// `cargo run -- revoke-api-key <id>` revokes one and `cargo run -- backfill` fills in what rows
+/// stored before measurements, ABVs and strengths were lack
+fn run_command(db: &repository::database::Database, args: &[String]) -> Result<(), String> {
+    match args {
+        [command, name, scopes] if command == "create-api-key" => {
+            let scopes = scopes.split(',')
+                .map(|x| x.parse::<models::api_keys::Scope>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| err.to_string())?;
+            let (api_key, key) = models::api_keys::ApiKey::create_key(db, name, &scopes)
+                .map_err(|err| err.to_string())?;
+            println!("Created API key {} ({}) with scopes {:?}", api_key.id, api_key.name, api_key.scopes);
+            println!("{}", key);
+            Ok(())
+        },
+        [command, id] if command == "revoke-api-key" => {
+            let id = id.parse::<uuid::Uuid>().map_err(|err| err.to_string())?;
+            let api_key = models::api_keys::ApiKey::revoke_key(db, id).map_err(|err| err.to_string())?;
+            println!("Revoked API key {} ({})", api_key.id, api_key.name);
+            Ok(())
+        },
+        [command] if command == "backfill" => {
+            let count = models::ingredients::Ingredient::backfill_measurements(db).map_err(|err| err.to_string())?;
+            println!("Parsed {} stored measurements", count);
+            let count = models::canonical_ingredients::CanonicalIngredient::backfill_abv(db).map_err(|err| err.to_string())?;
+            println!("Filled in the ABV of {} ingredients", count);
+            let count = models::cocktails::Cocktail::backfill_strength(db).map_err(|err| err.to_string())?;
+            println!("Estimated the strength of {} cocktails", count);
+            Ok(())
+        },
+        _ => Err("Usage: create-api-key <name> <read,write,generate> | revoke-api-key <id> | backfill".to_string()),
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let todo_db = repository::database::Database::new();
-    let app_data = web::Data::new(todo_db);
-
-    // let telemetry = telemetry::OpenTelemetryStack::new();
-    // let telemetry_data = web::Data::new(telemetry.clone());
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
+    config::init(config.clone());
 
+    let todo_db = repository::database::Database::new(&config.database)
+        .map_err(|err| std::io::Error::other(format!("Could not connect to the database: {}", err)))?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return run_command(&todo_db, &args)
+            .map_err(std::io::Error::other);
+    }
+    telemetry::init_logging(&config.telemetry);
+    // before the workers, their LLM metrics need the meter provider
+    let telemetry = telemetry::OpenTelemetryStack::new(todo_db.clone(), &config.telemetry);
+    let suggester = llm::suggester_from_config(&config.llm);
+    jobs::spawn_generation_workers(todo_db.clone(), suggester.clone(), config.llm.workers);
+    let llm_health_check = web::Data::new(api::health::LlmHealthCheck(
+        Some(suggester).filter(|_| config.llm.health_check)
+    ));
+    let app_data = web::Data::new(todo_db);
+    // shared by all workers so that limits are per server rather than per worker
+    let rate_limiter = web::Data::new(api::rate_limit::RateLimiter::from_config(&config.rate_limit));
 
-    HttpServer::new(move||{ 
-            // let cors = actix_cors::Cors::default().allowed_origin("http://localhost:3000/");
+    let cors_config = config.cors.clone();
 
+    let mut server = HttpServer::new(move||{ 
             App::new()
                 .app_data(app_data.clone())
-                // .app_data(telemetry_data.clone())
+                .app_data(rate_limiter.clone())
+                .app_data(llm_health_check.clone())
+                .configure(api::users::config)
                 .configure(api::todos::config)
+                // before cocktails, its /bartender scope would match these first
+                .configure(api::ingredients::config)
                 .configure(api::cocktails::config)
-                .service(healthcheck)
-                // .service(metrics)
+                .configure(api::health::config)
+                // public like /health, so Prometheus can scrape it without a key
+                .route("/metrics", web::get().to(telemetry.metrics_handler()))
                 .default_service(web::route().to(not_found))
-                // .wrap(cors)
-                .wrap(actix_web::middleware::Logger::default())
-                // .wrap(RequestTracing::new())
-                // .wrap(telemetry.metrics())
-        })
-        // change this to 0.0.0.0 on prod
-        // local should be 127.0.0.1
+                .wrap(RequestTracing::new())
+                .wrap(telemetry.metrics())
+                // outside the auth validator, preflight requests don't send a key
+                .wrap(Condition::new(api::cors::enabled(&cors_config), api::cors::cors(&cors_config)))
+                .wrap(telemetry::RequestLogger)
+        });
+    if let Some(workers) = config.server.workers {
+        server = server.workers(workers);
+    }
+    info!(host = %config.server.host, port = config.server.port, "listening");
+    let server = server
+        // 0.0.0.0 in containers, otherwise the port isn't reachable from outside
         // https://community.render.com/t/actix-web-4-0-failing-on-deploy/4486/3
-        .bind(("0.0.0.0", 8000))?
+        .bind((config.server.host.as_str(), config.server.port))?
         .run()
-        .await
+        .await;
+
+    telemetry::OpenTelemetryStack::shutdown();
+    server
 }
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, Selectable, Identifiable};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::repository::schema::api_keys::dsl::*;
+use crate::repository::database::Database;
+
+/// Prefix of every key, so they are easy to spot in config and logs
+const KEY_PREFIX: &str = "bt_";
+/// Random bytes in a key
+const KEY_BYTES: usize = 32;
+
+/// What an API key may do
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// fetch cocktails, ingredients, jobs and todos
+    Read,
+    /// create, change and delete them
+    Write,
+    /// ask the LLM for cocktails, which costs money
+    Generate,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Generate => "generate",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            "generate" => Ok(Scope::Generate),
+            _ => Err(ApiError::BadRequest(format!("Unknown scope {:?}, expected read, write or generate", s))),
+        }
+    }
+}
+
+/// A key clients authenticate with as `Authorization: Bearer <key>`. Only the hash of the key
+/// is stored.
+#[derive(Serialize, Debug, Clone, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = crate::repository::schema::api_keys)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl ApiKey {
+    /// Creates a key with `key_scopes`. Returns the key itself as well, which can't be
+    /// recovered later.
+    pub fn create_key(db: &Database, key_name: &str, key_scopes: &[Scope]) -> Result<(ApiKey, String), ApiError> {
+        if key_name.trim().is_empty() {
+            return Err(ApiError::BadRequest("name must not be empty".to_string()));
+        }
+
+        let mut bytes = [0u8; KEY_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let key = format!("{}{}", KEY_PREFIX, to_hex(&bytes));
+
+        let mut scope_names: Vec<String> = vec![];
+        for scope in key_scopes {
+            if !scope_names.iter().any(|x| x == scope.as_str()) {
+                scope_names.push(scope.to_string());
+            }
+        }
+
+        let api_key = ApiKey {
+            id: Uuid::new_v4(),
+            name: key_name.trim().to_string(),
+            key_hash: hash_key(&key),
+            scopes: scope_names,
+            revoked_at: None,
+            created_at: Some(Utc::now().naive_utc()),
+            updated_at: Some(Utc::now().naive_utc()),
+        };
+
+        let api_key = diesel::insert_into(api_keys)
+            .values(&api_key)
+            .get_result::<ApiKey>(&mut db.pool.get()?)?;
+        Ok((api_key, key))
+    }
+
+    /// The key `key` belongs to, unless it doesn't exist or was revoked
+    pub fn find_by_key(db: &Database, key: &str) -> Result<Option<ApiKey>, ApiError> {
+        let api_key = api_keys
+            .filter(key_hash.eq(hash_key(key)))
+            .filter(revoked_at.is_null())
+            .first::<ApiKey>(&mut db.pool.get()?)
+            .optional()?;
+        Ok(api_key)
+    }
+
+    pub fn revoke_key(db: &Database, key_id: Uuid) -> Result<ApiKey, ApiError> {
+        let api_key = diesel::update(api_keys.find(key_id))
+            .set(revoked_at.eq(Some(Utc::now().naive_utc())))
+            .get_result::<ApiKey>(&mut db.pool.get()?)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound("API key not found".to_string()))?;
+        Ok(api_key)
+    }
+
+    /// Whether `token` is one of our keys rather than a user's login token
+    pub fn is_api_key(token: &str) -> bool {
+        token.starts_with(KEY_PREFIX)
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|x| x == scope.as_str())
+    }
+}
+
+/// Keys are long and random, so a plain sha256 is enough to make a leaked table useless
+fn hash_key(key: &str) -> String {
+    to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+
+#[test]
+pub fn test_api_key_scopes() {
+    assert_eq!("Generate".parse::<Scope>().unwrap(), Scope::Generate);
+    assert!("admin".parse::<Scope>().is_err());
+
+    let api_key = ApiKey {
+        id: Uuid::new_v4(),
+        name: "test".to_owned(),
+        key_hash: hash_key("bt_test"),
+        scopes: vec!["read".to_owned(), "generate".to_owned()],
+        revoked_at: None,
+        created_at: None,
+        updated_at: None,
+    };
+    assert!(api_key.has_scope(Scope::Read));
+    assert!(!api_key.has_scope(Scope::Write));
+    assert_eq!(api_key.key_hash.len(), 64);
+    assert_ne!(hash_key("bt_test"), hash_key("bt_tesT"));
+}
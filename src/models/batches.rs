@@ -0,0 +1,167 @@
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+use super::cocktails::CocktailData;
+use super::ingredients::IngredientData;
+use super::measurements::{parse_volume, round_to, Measurement, Unit};
+use super::preparation::Method;
+
+/// Most servings a batch can be scaled to
+const MAX_SERVINGS: f64 = 1000.0;
+
+/// What a recipe is scaled to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchTarget {
+    Servings(f64),
+    /// total volume of the batch in ml, dilution included
+    Volume(f64),
+}
+
+impl BatchTarget {
+    /// Exactly one of `servings` and `total_volume` (e.g. "1l") has to be given
+    pub fn new(servings: Option<u32>, total_volume: Option<&str>) -> Result<BatchTarget, ApiError> {
+        match (servings, total_volume) {
+            (Some(servings), None) if servings > 0 && servings as f64 <= MAX_SERVINGS => {
+                Ok(BatchTarget::Servings(servings as f64))
+            },
+            (Some(_), None) => Err(ApiError::BadRequest(
+                format!("servings must be between 1 and {}", MAX_SERVINGS)
+            )),
+            (None, Some(volume)) => match parse_volume(volume) {
+                Some(ml) if ml > 0.0 && ml.is_finite() => Ok(BatchTarget::Volume(ml)),
+                _ => Err(ApiError::BadRequest(format!("Could not understand total_volume {:?}", volume))),
+            },
+            _ => Err(ApiError::BadRequest("Exactly one of servings and total_volume is required".to_string())),
+        }
+    }
+}
+
+/// A cocktail recipe scaled up for batching
+#[derive(Serialize, Debug, Clone)]
+pub struct BatchRecipe {
+    #[serde(flatten)]
+    pub cocktail: CocktailData,
+    pub servings: f64,
+    pub method: Method,
+    /// volume of the batch in ml, dilution water included
+    pub total_volume_ml: f64,
+    /// ingredients whose measurement couldn't be understood and were left as they were
+    pub unscaled: Vec<String>,
+}
+
+impl BatchRecipe {
+    /// Scales `cocktail` to `target`. Stirred drinks can't be stirred over ice once batched, so
+    /// the water the ice would have added is added as an ingredient instead.
+    pub fn new(mut cocktail: CocktailData, target: BatchTarget) -> Result<BatchRecipe, ApiError> {
+        let method = Method::from_instructions(&cocktail.instructions);
+        let dilution = match method {
+            Method::Stirred => method.dilution(),
+            _ => 0.0,
+        };
+
+        let measurements: Vec<Measurement> = cocktail.ingredients.iter().map(stored_measurement).collect();
+        let serving_ml: f64 = measurements.iter().filter_map(|x| x.volume_ml()).sum();
+
+        let servings = match target {
+            BatchTarget::Servings(servings) => servings,
+            BatchTarget::Volume(_) if serving_ml == 0.0 => {
+                return Err(ApiError::BadRequest(
+                    "The recipe has no measurable volume to scale to total_volume".to_string()
+                ));
+            },
+            BatchTarget::Volume(ml) => round_to(ml / (serving_ml * (1.0 + dilution)), 0.01),
+        };
+        if servings > MAX_SERVINGS {
+            return Err(ApiError::BadRequest(
+                format!("servings must be between 1 and {}, total_volume makes {}", MAX_SERVINGS, servings)
+            ));
+        }
+
+        let mut unscaled = vec![];
+        for (ing, measurement) in cocktail.ingredients.iter_mut().zip(&measurements) {
+            if measurement.quantity.is_none() {
+                if measurement.unit != Some(Unit::ToTaste) {
+                    unscaled.push(ing.name.clone());
+                }
+                continue;
+            }
+
+            let scaled = measurement.scale(servings);
+            set_measurement(ing, &scaled);
+        }
+
+        let water_ml = serving_ml * servings * dilution;
+        if water_ml > 0.0 {
+            // in the same units as the rest of the recipe
+            let water = if measurements.iter().any(|x| x.unit == Some(Unit::Oz)) {
+                Measurement { quantity: Some(water_ml / Unit::Oz.ml().unwrap_or(1.0)), quantity_max: None, unit: Some(Unit::Oz) }
+            } else {
+                Measurement { quantity: Some(water_ml), quantity_max: None, unit: Some(Unit::Ml) }
+            }.scale(1.0);
+
+            let mut ing = IngredientData::from(("Water".to_string(), String::new()));
+            set_measurement(&mut ing, &water);
+            cocktail.ingredients.push(ing);
+        }
+
+        Ok(BatchRecipe {
+            cocktail,
+            servings,
+            method,
+            total_volume_ml: round_to(serving_ml * servings + water_ml, 0.5),
+            unscaled,
+        })
+    }
+}
+
+fn stored_measurement(ing: &IngredientData) -> Measurement {
+    Measurement {
+        quantity: ing.quantity,
+        quantity_max: ing.quantity_max,
+        unit: ing.unit.as_deref().and_then(|x| x.parse::<Unit>().ok()),
+    }
+}
+
+fn set_measurement(ing: &mut IngredientData, measurement: &Measurement) {
+    ing.quantity = measurement.quantity;
+    ing.quantity_max = measurement.quantity_max;
+    ing.unit = measurement.unit.map(|x| x.as_str().to_string());
+    if let Some(rendered) = measurement.render() {
+        ing.measurement = rendered;
+    }
+}
+
+
+#[test]
+pub fn test_batch_stirred_cocktail() {
+    let mut cocktail = CocktailData::from((
+        "Manhattan".to_owned(),
+        vec![
+            ("Rye whiskey".to_owned(), "2 oz".to_owned()),
+            ("Sweet vermouth".to_owned(), "1 oz".to_owned()),
+            ("Angostura bitters".to_owned(), "2 dashes".to_owned()),
+            ("Cherry".to_owned(), "garnish".to_owned()),
+        ],
+        vec!["Stir with ice and strain into a chilled glass.".to_owned()],
+    ));
+    for ing in cocktail.ingredients.iter_mut() {
+        let parsed = Measurement::parse(&ing.measurement);
+        set_measurement(ing, &parsed);
+    }
+
+    let batch = BatchRecipe::new(cocktail.clone(), BatchTarget::Servings(10.0)).unwrap();
+    let measurements: Vec<&str> = batch.cocktail.ingredients.iter().map(|x| x.measurement.as_str()).collect();
+
+    assert_eq!(batch.method, Method::Stirred);
+    // 91.6 ml a drink, 20% of that again as water
+    assert_eq!(measurements, vec!["20 oz", "10 oz", "16 ml", "garnish", "6 oz"]);
+    assert_eq!(batch.unscaled, vec!["Cherry".to_owned()]);
+
+    let batch = BatchRecipe::new(cocktail.clone(), BatchTarget::Volume(1100.0)).unwrap();
+    assert_eq!(batch.servings, 10.01);
+
+    let too_much = BatchTarget::new(None, Some("100000l")).unwrap();
+    assert!(matches!(BatchRecipe::new(cocktail, too_much), Err(ApiError::BadRequest(_))));
+    assert!(BatchTarget::new(None, Some(&format!("{}ml", "9".repeat(400)))).is_err());
+}
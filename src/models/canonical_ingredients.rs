@@ -0,0 +1,366 @@
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Text};
+use diesel::{Queryable, QueryableByName, Insertable, RunQueryDsl, QueryDsl, Selectable, Identifiable};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config;
+use crate::error::ApiError;
+use crate::repository::schema::{canonical_ingredients, ingredient_aliases, ingredients};
+use crate::repository::database::Database;
+
+use super::cocktails::Cocktail;
+use super::strength::default_abv;
+
+// defined in the create_canonical_ingredients migration
+sql_function!(fn normalize_ingredient_name(raw: Text) -> Text);
+
+/// Longest alias once normalized, `ingredient_aliases.alias` is a varchar(100)
+const MAX_ALIAS_LEN: usize = 100;
+
+/// Trigram similarity threshold for fuzzy ingredient lookups, `ingredients.similarity_threshold`
+pub fn similarity_threshold() -> f64 {
+    config::get().ingredients.similarity_threshold
+}
+
+/// An ingredient in the catalogue that all spellings of it are linked to
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = canonical_ingredients)]
+pub struct CanonicalIngredient {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    /// percent alcohol by volume, `None` when unknown
+    pub abv: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = ingredient_aliases)]
+pub struct IngredientAlias {
+    pub alias: String,
+    pub canonical_ingredient_id: Uuid,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CanonicalIngredientData {
+    pub id: Uuid,
+    pub name: String,
+    pub abv: Option<f64>,
+    pub aliases: Vec<String>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+}
+
+/// A canonical ingredient whose name or aliases look like an autocomplete query
+#[derive(QueryableByName, Serialize, Debug, Clone)]
+pub struct IngredientSuggestion {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    /// 1.0 for prefix matches, the trigram similarity otherwise
+    #[diesel(sql_type = Double)]
+    pub score: f64,
+}
+
+#[derive(QueryableByName, Debug, Clone)]
+struct SimilarAlias {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    canonical_ingredient_id: Uuid,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewAlias {
+    pub alias: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CanonicalIngredientUpdate {
+    /// percent alcohol by volume, null when unknown
+    pub abv: Option<f64>,
+}
+
+impl CanonicalIngredientData {
+    fn new(canonical: CanonicalIngredient, aliases: Vec<String>) -> Self {
+        Self {
+            id: canonical.id,
+            name: canonical.name,
+            abv: canonical.abv,
+            aliases,
+            created_at: canonical.created_at,
+            updated_at: canonical.updated_at,
+        }
+    }
+}
+
+impl CanonicalIngredient {
+    /// Looks up the canonical ingredient `ing_name` is an alias of
+    pub fn find_id_by_name(conn: &mut PgConnection, ing_name: &str) -> Result<Option<Uuid>, ApiError> {
+        let c_id = ingredient_aliases::table
+            .filter(ingredient_aliases::alias.eq(normalize_ingredient_name(ing_name)))
+            .select(ingredient_aliases::canonical_ingredient_id)
+            .first::<Uuid>(conn)
+            .optional()?;
+        Ok(c_id)
+    }
+
+    /// Looks up the canonical ingredient with the alias most similar to `ing_name`, as long as
+    /// it is at least `threshold` similar, e.g. "lemmon juice" for "lemon juice"
+    pub fn find_similar_id(conn: &mut PgConnection, ing_name: &str, threshold: f64) -> Result<Option<Uuid>, ApiError> {
+        conn.transaction::<_, ApiError, _>(|conn| {
+            // % only uses the trigram index with the threshold set for the session or transaction
+            diesel::sql_query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+                .bind::<Text, _>(threshold.to_string())
+                .execute(conn)?;
+
+            let similar = diesel::sql_query(
+                "SELECT canonical_ingredient_id \
+                 FROM ingredient_aliases \
+                 WHERE alias % normalize_ingredient_name($1) \
+                 ORDER BY similarity(alias, normalize_ingredient_name($1)) DESC, alias \
+                 LIMIT 1"
+            )
+                .bind::<Text, _>(ing_name)
+                .get_result::<SimilarAlias>(conn)
+                .optional()?;
+
+            Ok(similar.map(|x| x.canonical_ingredient_id))
+        })
+    }
+
+    /// Looks up the canonical ingredient for `ing_name`, exactly or failing that by trigram
+    /// similarity
+    pub fn find_id_by_name_or_similar(conn: &mut PgConnection, ing_name: &str) -> Result<Option<Uuid>, ApiError> {
+        match CanonicalIngredient::find_id_by_name(conn, ing_name)? {
+            Some(c_id) => Ok(Some(c_id)),
+            None => CanonicalIngredient::find_similar_id(conn, ing_name, similarity_threshold()),
+        }
+    }
+
+    /// Canonical ids of the known ingredients in `ing_names`, matched exactly or failing that by
+    /// trigram similarity. Names that match nothing are left out.
+    pub fn find_ids_by_names(conn: &mut PgConnection, ing_names: &[String]) -> Result<Vec<Uuid>, ApiError> {
+        let mut ids: Vec<Uuid> = vec![];
+        for ing_name in ing_names {
+            if let Some(c_id) = CanonicalIngredient::find_id_by_name_or_similar(conn, ing_name)? {
+                if !ids.contains(&c_id) {
+                    ids.push(c_id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Returns the canonical id for `ing_name`, adding it to the catalogue when it is new
+    pub fn resolve(conn: &mut PgConnection, ing_name: &str) -> Result<Uuid, ApiError> {
+        if let Some(c_id) = CanonicalIngredient::find_id_by_name(conn, ing_name)? {
+            return Ok(c_id);
+        }
+
+        conn.transaction::<_, ApiError, _>(|conn| {
+            let canonical = diesel::insert_into(canonical_ingredients::table)
+                .values((
+                    canonical_ingredients::id.eq(Uuid::new_v4()),
+                    canonical_ingredients::name.eq(ing_name.split_whitespace().collect::<Vec<&str>>().join(" ")),
+                ))
+                .get_result::<CanonicalIngredient>(conn)?;
+
+            let inserted = diesel::insert_into(ingredient_aliases::table)
+                .values((
+                    ingredient_aliases::alias.eq(normalize_ingredient_name(ing_name)),
+                    ingredient_aliases::canonical_ingredient_id.eq(canonical.id),
+                ))
+                .on_conflict_do_nothing()
+                .returning(ingredient_aliases::alias)
+                .get_result::<String>(conn)
+                .optional()?;
+
+            if let Some(new_alias) = inserted {
+                if let Some(known_abv) = default_abv(&new_alias) {
+                    diesel::update(canonical_ingredients::table.find(canonical.id))
+                        .set(canonical_ingredients::abv.eq(known_abv))
+                        .execute(conn)?;
+                }
+                return Ok(canonical.id);
+            }
+
+            // added by someone else since we looked
+            diesel::delete(canonical_ingredients::table.find(canonical.id)).execute(conn)?;
+            CanonicalIngredient::find_id_by_name(conn, ing_name)?
+                .ok_or_else(|| ApiError::Internal(format!("Could not resolve ingredient {}", ing_name)))
+        })
+    }
+
+    /// Autocomplete for ingredient names. Aliases starting with `q` come first, followed by
+    /// aliases containing it or at least `similarity_threshold()` similar to it.
+    pub fn suggest(db: &Database, q: &str, limit: i64) -> Result<Vec<IngredientSuggestion>, ApiError> {
+        let mut conn = db.pool.get()?;
+        conn.transaction::<_, ApiError, _>(|conn| {
+            diesel::sql_query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+                .bind::<Text, _>(similarity_threshold().to_string())
+                .execute(conn)?;
+
+            let suggestions = diesel::sql_query(
+                "WITH query AS ( \
+                    SELECT normalize_ingredient_name($1) AS term, \
+                           replace(replace(replace(normalize_ingredient_name($1), '\\', '\\\\'), '%', '\\%'), '_', '\\_') AS pattern \
+                 ) \
+                 SELECT c.id, c.name, \
+                        MAX(CASE WHEN a.alias ILIKE query.pattern || '%' THEN 1.0 \
+                                 ELSE similarity(a.alias, query.term) END)::float8 AS score \
+                 FROM query, ingredient_aliases a \
+                 JOIN canonical_ingredients c ON c.id = a.canonical_ingredient_id \
+                 WHERE a.alias ILIKE '%' || query.pattern || '%' OR a.alias % query.term \
+                 GROUP BY c.id, c.name \
+                 ORDER BY score DESC, c.name \
+                 LIMIT $2"
+            )
+                .bind::<Text, _>(q)
+                .bind::<BigInt, _>(limit)
+                .get_results::<IngredientSuggestion>(conn)?;
+
+            Ok(suggestions)
+        })
+    }
+
+    pub fn get_canonical_ingredients(db: &Database) -> Result<Vec<CanonicalIngredientData>, ApiError> {
+        let mut conn = db.pool.get()?;
+        let canonicals = canonical_ingredients::table
+            .order(canonical_ingredients::name.asc())
+            .load::<CanonicalIngredient>(&mut conn)?;
+        let aliases = ingredient_aliases::table
+            .order(ingredient_aliases::alias.asc())
+            .load::<IngredientAlias>(&mut conn)?;
+
+        let results = canonicals.into_iter().map(|canonical| {
+            let names = aliases.iter()
+                .filter(|x| x.canonical_ingredient_id == canonical.id)
+                .map(|x| x.alias.clone())
+                .collect();
+            CanonicalIngredientData::new(canonical, names)
+        }).collect();
+        Ok(results)
+    }
+
+    pub fn get_canonical_ingredient_by_id(db: &Database, c_id: Uuid) -> Result<CanonicalIngredientData, ApiError> {
+        let mut conn = db.pool.get()?;
+        let canonical = canonical_ingredients::table.find(c_id)
+            .get_result::<CanonicalIngredient>(&mut conn)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound("Canonical ingredient not found".to_string()))?;
+        let aliases = ingredient_aliases::table
+            .filter(ingredient_aliases::canonical_ingredient_id.eq(c_id))
+            .select(ingredient_aliases::alias)
+            .order(ingredient_aliases::alias.asc())
+            .load::<String>(&mut conn)?;
+
+        Ok(CanonicalIngredientData::new(canonical, aliases))
+    }
+
+    /// Makes `new_alias` a spelling of the canonical ingredient `c_id`. Ingredients stored under
+    /// that spelling are relinked, and a canonical ingredient left without aliases is removed.
+    pub fn add_alias(db: &Database, c_id: Uuid, new_alias: &str) -> Result<CanonicalIngredientData, ApiError> {
+        if new_alias.trim().is_empty() {
+            return Err(ApiError::BadRequest("alias must not be empty".to_string()));
+        }
+
+        db.pool.get()?.transaction::<_, ApiError, _>(|conn| {
+            canonical_ingredients::table.find(c_id)
+                .get_result::<CanonicalIngredient>(conn)
+                .optional()?
+                .ok_or_else(|| ApiError::NotFound("Canonical ingredient not found".to_string()))?;
+
+            let alias = diesel::select(normalize_ingredient_name(new_alias)).get_result::<String>(conn)?;
+            if alias.chars().count() > MAX_ALIAS_LEN {
+                return Err(ApiError::BadRequest(format!("alias is longer than {} characters", MAX_ALIAS_LEN)));
+            }
+
+            let previous = CanonicalIngredient::find_id_by_name(conn, new_alias)?;
+
+            diesel::insert_into(ingredient_aliases::table)
+                .values((
+                    ingredient_aliases::alias.eq(&alias),
+                    ingredient_aliases::canonical_ingredient_id.eq(c_id),
+                ))
+                .on_conflict(ingredient_aliases::alias)
+                .do_update()
+                .set(ingredient_aliases::canonical_ingredient_id.eq(c_id))
+                .execute(conn)?;
+
+            diesel::update(ingredients::table)
+                .filter(normalize_ingredient_name(ingredients::name).eq(&alias))
+                .set(ingredients::canonical_ingredient_id.eq(c_id))
+                .execute(conn)?;
+            Cocktail::update_strength_by_canonical_id(conn, &c_id)?;
+
+            if let Some(p_id) = previous.filter(|p_id| *p_id != c_id) {
+                let remaining = ingredient_aliases::table
+                    .filter(ingredient_aliases::canonical_ingredient_id.eq(p_id))
+                    .count()
+                    .get_result::<i64>(conn)?;
+                if remaining == 0 {
+                    diesel::delete(canonical_ingredients::table.find(p_id)).execute(conn)?;
+                }
+            }
+
+            Ok(())
+        })?;
+
+        CanonicalIngredient::get_canonical_ingredient_by_id(db, c_id)
+    }
+
+    /// Sets the ABV of `c_id` and recomputes the strength of the cocktails using it
+    pub fn update_canonical_ingredient(
+        db: &Database,
+        c_id: Uuid,
+        update: CanonicalIngredientUpdate,
+    ) -> Result<CanonicalIngredientData, ApiError> {
+        if update.abv.is_some_and(|x| !(0.0..=100.0).contains(&x)) {
+            return Err(ApiError::BadRequest("abv must be between 0 and 100".to_string()));
+        }
+
+        db.pool.get()?.transaction::<_, ApiError, _>(|conn| {
+            let count = diesel::update(canonical_ingredients::table.find(c_id))
+                .set(canonical_ingredients::abv.eq(update.abv))
+                .execute(conn)?;
+
+            if count == 0 {
+                return Err(ApiError::NotFound("Canonical ingredient not found".to_string()));
+            }
+
+            Cocktail::update_strength_by_canonical_id(conn, &c_id)
+        })?;
+
+        CanonicalIngredient::get_canonical_ingredient_by_id(db, c_id)
+    }
+
+    /// Fills in the ABV of well known ingredients added before ABVs were stored, returns how
+    /// many were filled in
+    pub fn backfill_abv(db: &Database) -> Result<usize, ApiError> {
+        let mut conn = db.pool.get()?;
+        let unknown = ingredient_aliases::table
+            .inner_join(canonical_ingredients::table)
+            .filter(canonical_ingredients::abv.is_null())
+            .select((ingredient_aliases::alias, ingredient_aliases::canonical_ingredient_id))
+            .load::<(String, Uuid)>(&mut conn)?;
+
+        let mut updated: Vec<Uuid> = vec![];
+        for (known_alias, c_id) in unknown {
+            let known_abv = match default_abv(&known_alias) {
+                Some(known_abv) if !updated.contains(&c_id) => known_abv,
+                _ => continue,
+            };
+
+            diesel::update(canonical_ingredients::table.find(c_id))
+                .set(canonical_ingredients::abv.eq(known_abv))
+                .execute(&mut conn)?;
+            Cocktail::update_strength_by_canonical_id(&mut conn, &c_id)?;
+            updated.push(c_id);
+        }
+
+        Ok(updated.len())
+    }
+}
@@ -1,30 +1,61 @@
 
-use chrono::Utc;
-use diesel::result::Error;
-use openai_api_rust::completions::{CompletionsBody, CompletionsApi};
-use openai_api_rust::{Auth, OpenAI};
+use chrono::{NaiveTime, Utc};
+use diesel::pg::Pg;
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
 // Note: prelude is required to use things like column.eq_any(vec)
+use diesel::sql_types::Text;
+use diesel_filter::{DieselFilter, Paginate};
 use diesel::{prelude::*, Queryable, Insertable, AsChangeset, RunQueryDsl, QueryDsl, Selectable, Identifiable};
 
-use crate::api::cocktails::GenerateQuery;
+use crate::api::cocktails::{CocktailsQuery, GenerateQuery};
+use crate::error::ApiError;
+use crate::llm::parse::{parse_cocktails, ParseError};
+use crate::repository::schema;
+use crate::repository::schema::cocktails;
 use crate::repository::schema::cocktails::dsl::*;
 use crate::repository::schema::cocktails::columns::id;
 use crate::repository::database::Database;
 
+use super::canonical_ingredients::CanonicalIngredient;
+use super::cursors::GenerateCursor;
+use super::generation_jobs::{GenerationJob, JOB_DONE, JOB_PENDING, JOB_RUNNING};
 use super::ingredients::{Ingredient, IngredientData};
 use super::instructions::{Instruction, InstructionData};
-
-
-#[derive(Serialize, Selectable, Identifiable, Deserialize, Debug, Clone, Queryable, Insertable, AsChangeset)]
-#[diesel(table_name = crate::repository::schema::cocktails)]
+use super::llm_usage::{daily_llm_quota, LlmUsage};
+use super::measurements::{Measurement, Unit, UnitSystem};
+use super::preparation::Method;
+use super::strength::Strength;
+use super::similarity::{ingredient_overlap, name_similarity};
+
+// defined in the normalize_cocktail_names migration
+sql_function!(fn normalize_cocktail_name(raw: Text) -> Text);
+
+/// Cocktails on a page of the list unless per_page says otherwise
+const DEFAULT_PER_PAGE: i64 = 20;
+const MAX_PER_PAGE: i64 = 100;
+
+/// How alike names have to be for generated cocktails to be checked for duplicates
+const DUPLICATE_NAME_SIMILARITY: f64 = 0.8;
+/// Share of ingredients a similarly named cocktail has to share to be a duplicate
+const DUPLICATE_INGREDIENT_OVERLAP: f64 = 0.75;
+
+#[derive(Serialize, Selectable, Identifiable, Deserialize, Debug, Clone, Queryable, Insertable, AsChangeset, DieselFilter)]
+#[diesel(table_name = cocktails)]
+#[pagination]
 pub struct Cocktail {
     #[serde(default)]
     pub id: Uuid,
+    #[filter(substring, insensitive)]
     pub name: String,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
+    /// estimated percent alcohol by volume once mixed, see `Cocktail::update_strength`
+    pub abv: Option<f64>,
+    pub standard_drinks: Option<f64>,
+    /// the user that added the cocktail, none for generated ones
+    pub created_by: Option<Uuid>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -35,6 +66,124 @@ pub struct CocktailData {
     pub updated_at: Option<chrono::NaiveDateTime>,
     pub instructions: Vec<InstructionData>,
     pub ingredients: Vec<IngredientData>,
+    /// estimated from the ingredients, ignored when writing
+    pub abv: Option<f64>,
+    pub standard_drinks: Option<f64>,
+    /// set from the logged in user, ignored when writing
+    pub created_by: Option<Uuid>,
+}
+
+/// Orders for the cocktail list, a leading `-` sorts descending
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CocktailSort {
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "-name")]
+    NameDesc,
+    #[serde(rename = "created_at")]
+    CreatedAt,
+    #[serde(rename = "-created_at")]
+    CreatedAtDesc,
+    #[serde(rename = "abv")]
+    Abv,
+    #[serde(rename = "-abv")]
+    AbvDesc,
+}
+
+/// What the cocktail list can include on top of the cocktails themselves
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CocktailInclude {
+    Ingredients,
+    Instructions,
+}
+
+/// A cocktail in the list, with its ingredients and instructions if they were asked for
+#[derive(Serialize, Debug, Clone)]
+pub struct CocktailEntry {
+    #[serde(flatten)]
+    pub cocktail: Cocktail,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingredients: Option<Vec<IngredientData>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<Vec<InstructionData>>,
+}
+
+/// One page of the cocktail list
+#[derive(Serialize, Debug, Clone)]
+pub struct CocktailPage {
+    pub cocktails: Vec<CocktailEntry>,
+    /// cocktails matching the filters across all pages
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+impl CocktailPage {
+    pub fn next_page(&self) -> Option<i64> {
+        (self.page * self.per_page < self.total).then_some(self.page + 1)
+    }
+
+    pub fn prev_page(&self) -> Option<i64> {
+        let last_page = (self.total + self.per_page - 1) / self.per_page;
+        (self.page > 1).then_some((self.page - 1).min(last_page.max(1)))
+    }
+}
+
+/// A cocktail returned from generate along with how well it fits the requested ingredients
+#[derive(Serialize, Debug, Clone)]
+pub struct CocktailMatch {
+    #[serde(flatten)]
+    pub cocktail: CocktailData,
+    /// ingredients of the cocktail that were requested
+    pub matched: Vec<String>,
+    /// ingredients of the cocktail that were not requested
+    pub missing: Vec<String>,
+    /// share of the cocktail's ingredients that were requested (0.0 - 1.0)
+    pub coverage: f64,
+}
+
+impl CocktailMatch {
+    /// `requested` are the canonical ids of the requested ingredients
+    pub fn new(cocktail: CocktailData, requested: &[Uuid]) -> Self {
+        let (matched, missing): (Vec<&IngredientData>, Vec<&IngredientData>) = cocktail.ingredients.iter()
+            .partition(|ing| ing.canonical_ingredient_id.is_some_and(|c_id| requested.contains(&c_id)));
+        let matched: Vec<String> = matched.into_iter().map(|ing| ing.name.clone()).collect();
+        let missing: Vec<String> = missing.into_iter().map(|ing| ing.name.clone()).collect();
+
+        let coverage = if cocktail.ingredients.is_empty() {
+            0.0
+        } else {
+            matched.len() as f64 / cocktail.ingredients.len() as f64
+        };
+
+        Self { cocktail, matched, missing, coverage }
+    }
+}
+
+/// What became of the cocktails the LLM suggested
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedCocktails {
+    /// the stored cocktails, new or not
+    pub ids: Vec<Uuid>,
+    pub created: usize,
+    /// suggestions that were already stored
+    pub duplicates: usize,
+}
+
+/// A page of cocktails for a generate request, plus the job that will generate more once the
+/// stored ones run out
+#[derive(Serialize, Debug, Clone)]
+pub struct GenerateResult {
+    pub cocktails: Vec<CocktailMatch>,
+    /// poll /bartender/generation-jobs/{job_id} for the generated cocktails
+    pub job_id: Option<Uuid>,
+    /// pass as `cursor` for the next page, none once there is nothing more to return
+    pub cursor: Option<String>,
+    /// the daily quota of LLM generations is used up, so only stored cocktails were returned
+    pub quota_exceeded: bool,
 }
 
 impl From<(String, Vec<(String, String)>, Vec<String>)> for CocktailData {
@@ -51,61 +200,179 @@ impl From<(String, Vec<(String, String)>, Vec<String>)> for CocktailData {
                 .collect(),
             created_at: None,
             updated_at: None,
+            abv: None,
+            standard_drinks: None,
+            created_by: None,
         }
     }
 }
 
 impl CocktailData {
-    pub fn parse_message(message: &str) -> Vec<CocktailData> {
-        serde_json::from_str::<Vec<(String,Vec<(String, String)>, Vec<String>)>>(message).unwrap()
-            .iter()
-            .map(|message| { CocktailData::from(message.to_owned()) })
-            .collect()
+    pub fn convert_units(&mut self, system: UnitSystem) {
+        self.ingredients.iter_mut().for_each(|x| x.convert_units(system));
+    }
+
+    pub fn parse_message(message: &str) -> Result<Vec<CocktailData>, ParseError> {
+        parse_cocktails(message)
     }
 }
 
 impl Cocktail {
-    pub fn get_cocktails(db: &Database) -> Vec<Cocktail> {
-        cocktails
-            .load::<Cocktail>(&mut db.pool.get().unwrap())
-            .expect("Error loading all cocktails")
+    /// A page of the cocktails matching `filters` and `query`. Cocktails whose strength isn't
+    /// known are left out when filtering on `max_abv`.
+    pub fn get_cocktails(db: &Database, filters: &CocktailFilters, query: &CocktailsQuery) -> Result<CocktailPage, ApiError> {
+        let page = filters.page.unwrap_or(1);
+        let per_page = filters.per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if page < 1 {
+            return Err(ApiError::BadRequest("page must be at least 1".to_string()));
+        }
+        if !(1..=MAX_PER_PAGE).contains(&per_page) {
+            return Err(ApiError::BadRequest(format!("per_page must be between 1 and {}", MAX_PER_PAGE)));
+        }
+        // the offset and the next page's are computed from it
+        if page.checked_mul(per_page).is_none() {
+            return Err(ApiError::BadRequest("page is too large".to_string()));
+        }
+
+        let mut conn = db.pool.get()?;
+        let mut c_ing_ids: Vec<Uuid> = vec![];
+        for ing_name in &query.ingredients {
+            match CanonicalIngredient::find_id_by_name_or_similar(&mut conn, ing_name)? {
+                Some(c_ing_id) => c_ing_ids.push(c_ing_id),
+                // nothing can have an ingredient we don't know
+                None => return Ok(CocktailPage { cocktails: vec![], total: 0, page, per_page, next: None, prev: None }),
+            }
+        }
+
+        let (results, mut total) = Cocktail::sorted(Cocktail::list_query(filters, query, &c_ing_ids), query.sort)
+            .paginate(Some(page))
+            .per_page(Some(per_page))
+            .load_and_count::<Cocktail>(&mut conn)?;
+
+        // the total comes with the rows, past the last page there are none
+        if results.is_empty() && page > 1 {
+            total = Cocktail::list_query(filters, query, &c_ing_ids)
+                .count()
+                .get_result::<i64>(&mut conn)?;
+        }
+
+        let mut ings = if query.include.contains(&CocktailInclude::Ingredients) {
+            Some(Ingredient::get_ingredients_by_cocktails(&mut conn, &results)?.into_iter())
+        } else {
+            None
+        };
+        let mut ins = if query.include.contains(&CocktailInclude::Instructions) {
+            Some(Instruction::get_instructions_by_cocktails(&mut conn, &results)?.into_iter())
+        } else {
+            None
+        };
+        let entries = results.into_iter()
+            .map(|cocktail| CocktailEntry {
+                cocktail,
+                ingredients: ings.as_mut().and_then(|x| x.next()).map(Ingredient::map_ingredients_to_data),
+                instructions: ins.as_mut().and_then(|x| x.next()).map(Instruction::map_instructions_to_data),
+            })
+            .collect();
+
+        Ok(CocktailPage { cocktails: entries, total, page, per_page, next: None, prev: None })
+    }
+
+    fn list_query<'a>(
+        filters: &'a CocktailFilters,
+        query: &CocktailsQuery,
+        c_ing_ids: &[Uuid],
+    ) -> cocktails::BoxedQuery<'a, Pg> {
+        let mut list = Cocktail::filter(filters);
+
+        if let Some(max_abv) = query.max_abv {
+            list = list.filter(abv.le(max_abv));
+        }
+        if let Some(from) = query.created_from {
+            list = list.filter(created_at.ge(from.and_time(NaiveTime::MIN)));
+        }
+        if let Some(to) = query.created_to.and_then(|x| x.succ_opt()) {
+            list = list.filter(created_at.lt(to.and_time(NaiveTime::MIN)));
+        }
+        for c_ing_id in c_ing_ids {
+            let with_ingredient = schema::ingredients::table
+                .filter(schema::ingredients::canonical_ingredient_id.eq(*c_ing_id))
+                .select(schema::ingredients::cocktail_id);
+            list = list.filter(id.eq_any(with_ingredient));
+        }
+        list
+    }
+
+    fn sorted(list: cocktails::BoxedQuery<Pg>, sort: Option<CocktailSort>) -> cocktails::BoxedQuery<Pg> {
+        // id last so that pages don't shift around between equal values
+        match sort.unwrap_or(CocktailSort::Name) {
+            CocktailSort::Name => list.order((name.asc(), id.asc())),
+            CocktailSort::NameDesc => list.order((name.desc(), id.asc())),
+            CocktailSort::CreatedAt => list.order((created_at.asc(), id.asc())),
+            CocktailSort::CreatedAtDesc => list.order((created_at.desc(), id.asc())),
+            CocktailSort::Abv => list.order((abv.asc().nulls_last(), id.asc())),
+            CocktailSort::AbvDesc => list.order((abv.desc().nulls_last(), id.asc())),
+        }
     }
 
-    pub fn get_cocktail_by_id(db: &Database, c_id: Uuid) -> Option<CocktailData> {
-        let mut conn = db.pool.get().unwrap();
+    pub fn get_cocktail_by_id(db: &Database, c_id: Uuid) -> Result<CocktailData, ApiError> {
+        let mut conn = db.pool.get()?;
         let cocktail = cocktails.find(&c_id)
             .get_result::<Cocktail>(&mut conn)
-            .expect("Error loading cocktail by id");
-
-        let ingredients = Ingredient::get_ingredients_by_cocktail_id(db, &c_id).unwrap();
-        // TODO: fix me
-        // let mapped_ings = ingredients.iter().map(|x| IngredientData::from(x)).collect();
-        let mapped_ings = Ingredient::map_ingredients_to_data(ingredients);
-        
-        let instructions = Instruction::get_instructions_by_cocktail_id(db, &c_id).unwrap();
-        // TODO: fix me
-        // let mapped_ins = instructions.iter().map(|x| InstructionData::from(x.clone())).collect();
-        let mapped_ins = Instruction::map_instructions_to_data(instructions);
-
-
-        Some(CocktailData { 
-            id: Some(cocktail.id), 
-            name: cocktail.name,
-            created_at: cocktail.created_at,
-            updated_at: cocktail.updated_at,
-            ingredients: mapped_ings, 
-            instructions: mapped_ins, 
-        })
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound("Cocktail not found".to_string()))?;
+
+        let mut data = Cocktail::load_data(&mut conn, vec![cocktail])?;
+        data.pop().ok_or_else(|| ApiError::Internal("Cocktail went missing".to_string()))
+    }
+
+    /// The cocktails `c_ids` in that order, leaving out any that don't exist. Three queries
+    /// however many there are.
+    pub fn get_cocktails_by_ids(conn: &mut PgConnection, c_ids: &[Uuid]) -> Result<Vec<CocktailData>, ApiError> {
+        let mut list = cocktails.filter(id.eq_any(c_ids))
+            .get_results::<Cocktail>(conn)?;
+
+        // eq_any doesn't keep the order so put it back
+        list.sort_by_key(|x| c_ids.iter().position(|c_id| *c_id == x.id));
+
+        Cocktail::load_data(conn, list)
+    }
+
+    /// Loads the ingredients and instructions of `list` with a query each
+    fn load_data(conn: &mut PgConnection, list: Vec<Cocktail>) -> Result<Vec<CocktailData>, ApiError> {
+        let ings = Ingredient::get_ingredients_by_cocktails(conn, &list)?;
+        let ins = Instruction::get_instructions_by_cocktails(conn, &list)?;
+
+        let data = list.into_iter()
+            .zip(ings)
+            .zip(ins)
+            .map(|((cocktail, c_ings), c_ins)| CocktailData {
+                id: Some(cocktail.id),
+                name: cocktail.name,
+                created_at: cocktail.created_at,
+                updated_at: cocktail.updated_at,
+                ingredients: Ingredient::map_ingredients_to_data(c_ings),
+                instructions: Instruction::map_instructions_to_data(c_ins),
+                abv: cocktail.abv,
+                standard_drinks: cocktail.standard_drinks,
+                created_by: cocktail.created_by,
+            })
+            .collect();
+        Ok(data)
     }
     
-    pub fn create_cocktail(db: &Database, new_cocktail: CocktailData) -> Result<Cocktail, Error> {
-        let mut con = db.pool.get().unwrap();
+    /// Stores `new_cocktail` with its ingredients and instructions. `user_id` is the user adding
+    /// it, if any.
+    pub fn create_cocktail(db: &Database, new_cocktail: CocktailData, user_id: Option<Uuid>) -> Result<Cocktail, ApiError> {
+        let mut con = db.pool.get()?;
 
         let cocktail = Cocktail {
             id: uuid::Uuid::new_v4(),
             name: new_cocktail.name,
             created_at: Some(Utc::now().naive_utc()),
             updated_at: Some(Utc::now().naive_utc()),
+            abv: None,
+            standard_drinks: None,
+            created_by: user_id,
         };
 
         let cocktail_ingredients = Ingredient::map_data_to_ingredients(
@@ -117,157 +384,359 @@ impl Cocktail {
             &cocktail.id
         );
 
-        diesel::insert_into(cocktails)
-            .values(&cocktail)
-            .execute(&mut con) 
-            .expect("Error creating new cocktail");
+        // cocktail, ingredients and instructions are written together or not at all
+        let cocktail = con.transaction::<_, ApiError, _>(|conn| {
+            diesel::insert_into(cocktails)
+                .values(&cocktail)
+                .execute(conn)?;
 
-        let ingredients_insert = Ingredient::create_ingredients(db, cocktail_ingredients);
+            Ingredient::create_ingredients(conn, cocktail_ingredients)?;
+            Instruction::create_instructions(conn, cocktail_instructions)?;
+            Cocktail::update_strength(conn, &cocktail.id)?;
 
-        match ingredients_insert { 
-            Err(msg) => {
-                Cocktail::delete_cocktail(db, cocktail.id);
-                return Err(msg);
-            },
-            _ => (),
-        };
+            let cocktail = cocktails.find(&cocktail.id).get_result::<Cocktail>(conn)?;
+            Ok(cocktail)
+        })?;
 
-        let instructions_insert = Instruction::create_instructions(db, cocktail_instructions);
+        Ok(cocktail)
+    }
 
-        match instructions_insert { 
-            Err(msg) => {
-                Cocktail::delete_cocktail(db, cocktail.id);
-                return Err(msg);
-            },
-            _ => (),
-        };
+    /// Recomputes the stored ABV and standard drinks of `c_id` from its ingredients and method
+    pub fn update_strength(conn: &mut PgConnection, c_id: &Uuid) -> Result<(), ApiError> {
+        let ing_rows = schema::ingredients::table
+            .left_join(schema::canonical_ingredients::table)
+            .filter(schema::ingredients::cocktail_id.eq(c_id))
+            .select((
+                schema::ingredients::quantity,
+                schema::ingredients::quantity_max,
+                schema::ingredients::unit,
+                schema::canonical_ingredients::abv.nullable(),
+            ))
+            .load::<(Option<f64>, Option<f64>, Option<String>, Option<f64>)>(conn)?;
+        let steps = schema::instructions::table
+            .filter(schema::instructions::cocktail_id.eq(c_id))
+            .order(schema::instructions::step.asc())
+            .select(schema::instructions::instruction)
+            .load::<String>(conn)?;
+
+        let measured: Vec<(Measurement, Option<f64>)> = ing_rows.into_iter()
+            .map(|(q, q_max, u, ing_abv)| {
+                let m = Measurement {
+                    quantity: q,
+                    quantity_max: q_max,
+                    unit: u.and_then(|x| x.parse::<Unit>().ok()),
+                };
+                (m, ing_abv)
+            })
+            .collect();
+        let steps: Vec<&str> = steps.iter().map(|x| x.as_str()).collect();
+        let strength = Strength::estimate(&measured, Method::from_steps(&steps));
+
+        diesel::update(cocktails.find(c_id))
+            .set((
+                abv.eq(strength.map(|x| x.abv)),
+                standard_drinks.eq(strength.map(|x| x.standard_drinks)),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
 
-        Ok(cocktail)
+    /// Recomputes the strength of every cocktail using the canonical ingredient `c_ing_id`
+    pub fn update_strength_by_canonical_id(conn: &mut PgConnection, c_ing_id: &Uuid) -> Result<(), ApiError> {
+        let c_ids = schema::ingredients::table
+            .filter(schema::ingredients::canonical_ingredient_id.eq(c_ing_id))
+            .select(schema::ingredients::cocktail_id)
+            .distinct()
+            .load::<Uuid>(conn)?;
+
+        for c_id in c_ids {
+            Cocktail::update_strength(conn, &c_id)?;
+        }
+        Ok(())
     }
 
-    pub fn delete_cocktail(db: &Database, c_id: Uuid) -> Option<usize> {
-        let count = diesel::delete(cocktails.find(c_id))
-            .execute(&mut db.pool.get().unwrap())
-            .expect("Error deleting cocktail");
+    /// Recomputes the strength of every cocktail, for ones stored before it was or before the
+    /// estimate changed. Returns how many have one.
+    pub fn backfill_strength(db: &Database) -> Result<usize, ApiError> {
+        let mut conn = db.pool.get()?;
+        let c_ids = cocktails.select(id).load::<Uuid>(&mut conn)?;
+
+        for c_id in &c_ids {
+            Cocktail::update_strength(&mut conn, c_id)?;
+        }
 
-        Some(count)
+        let count = cocktails.filter(abv.is_not_null())
+            .count()
+            .get_result::<i64>(&mut conn)?;
+        Ok(count as usize)
     }
 
-    pub fn update_cocktail(db: &Database, cocktail: Cocktail) -> Option<Cocktail> {
-        let updated_cocktail = diesel::update(cocktails.find(&cocktail.id))
-            .set(&cocktail)
-            .get_result::<Cocktail>(&mut db.pool.get().unwrap())
-            .expect("Error updating cocktail");
+    pub fn delete_cocktail(db: &Database, c_id: Uuid) -> Result<usize, ApiError> {
+        let count = diesel::delete(cocktails.find(c_id))
+            .execute(&mut db.pool.get()?)?;
+
+        if count == 0 {
+            return Err(ApiError::NotFound("Cocktail not found".to_string()));
+        }
 
-        Some(updated_cocktail)
+        Ok(count)
     }
-    
-    pub fn ask_gpt_for_cocktails(ingredients: &Vec<String>) -> Vec<CocktailData> {
-        let ingredients_str = ingredients.join(",");
-        // let proompt = format!("What cockatils can I make with these ingredients? [{},ice] Format the result as a json array like this [[name,[ingredents list],[instructions list]],...]. Where 'name' is the cocktail name string, 'ingredients list' is an array of ingredient name and measurement tuples, and instructions is an array of instruction strings. Limit to 2 cocktails.", ingredients_str);
-        // let proompt = format!("What cockatils can I make with these ingredients? [{},ice] Format the response as a JSON array of tuples, where each tuple has the format [name, [[ingredient_name, amount], ...], [instruction, ...]].  Limit to 2 cocktails.", ingredients_str);
-        let proompt = format!("What cockatils can I make with these ingredients? [{},ice] Format the response as a JSON array of tuples, where each tuple has the format [name_string, [[ingredient_name_string, amount_string], ...], [instruction_string, ...]]. Where 'name' is the cocktail name string, 'ingredients list' is an array of ingredient name and measurement tuples, and instructions is an array of instruction strings. Limit to 2 cocktails.", ingredients_str);
-        println!("proompt: {}", proompt);
-        let auth = Auth::from_env().unwrap();
-        let openai = OpenAI::new(auth, "https://api.openai.com/v1/");
-        let body = CompletionsBody {
-            model: "text-davinci-003".to_string(),
-            max_tokens: Some(256),
-            temperature: Some(0.6),
-            n: Some(1),
-            frequency_penalty: None,
-            presence_penalty: None,
-            top_p: Some(1_f32),
-            stream: Some(false),
-            stop: None,
-            logit_bias: None,
-            user: None,
-            suffix: None,
-            logprobs: None,
-            best_of: None,
-            echo: None,
-            prompt: Some(vec![proompt]),
-        };
 
-        let rs = openai.completion_create(&body);
-        let choice = rs.unwrap().choices;
-        let message = &choice[0].text.as_ref().unwrap();
-        println!("[Cocktails][ask_gpt_for_cocktails] message {:?}", message);
-        CocktailData::parse_message(message)
+    pub fn update_cocktail(db: &Database, c_id: Uuid, updated_cocktail: CocktailData) -> Result<CocktailData, ApiError> {
+        // released before reading the result back, which takes a connection of its own
+        db.pool.get()?.transaction::<_, ApiError, _>(|conn| {
+            let count = diesel::update(cocktails.find(&c_id))
+                .set(name.eq(&updated_cocktail.name))
+                .execute(conn)?;
+
+            if count == 0 {
+                return Err(ApiError::NotFound("Cocktail not found".to_string()));
+            }
+
+            Ingredient::sync_ingredients(conn, &c_id, updated_cocktail.ingredients)?;
+            Instruction::sync_instructions(conn, &c_id, updated_cocktail.instructions)?;
+            Cocktail::update_strength(conn, &c_id)?;
+
+            Ok(())
+        })?;
+
+        Cocktail::get_cocktail_by_id(db, c_id)
     }
     
-    // TODO: Implement pagination. Once on last page of results, start asking Chat GPT
-    pub fn generate_cocktails(db: &Database, query: &GenerateQuery) -> Result<Vec<CocktailData>, Error> {
-        println!("[Cocktail][generate_cocktails] ingredients: {:?}", query.ingredients);
+    /// Returns a page of the stored cocktails that best match the requested ingredients, in
+    /// ranked order from `query.cursor`. Once they are exhausted a generation job is queued to
+    /// ask the LLM for more, and the following pages return what it came up with. Jobs count
+    /// against the daily quota of `subject`.
+    pub fn generate_cocktails(db: &Database, query: &GenerateQuery, subject: &str) -> Result<GenerateResult, ApiError> {
+        debug!(ingredients = ?query.ingredients, "generating cocktails");
         let mut limit = query.pagesize.unwrap_or(2);
 
         if limit > 10 { 
             limit = 10;
         }
-        // 1. get all ingredients in db with similar or the same name
-        let ingredients = Ingredient::get_ingredients_by_names(db, &query.ingredients)?;
-        println!("[Cocktail][generate_cocktails] ingredients: {:?}", ingredients);
-        
-        let c_ids: Vec<Uuid> = ingredients.iter().map(|x| x.cocktail_id).collect();
-
-        let c = cocktails.filter(id.eq_any(c_ids))
-            .offset(query.pagestart.unwrap_or(0) as i64)
-            .limit(limit as i64)
-            .get_results::<Cocktail>(&mut db.pool.get().unwrap())?;
-
-        let mut cocktail_vec: Vec<CocktailData> = c.iter().map(|x| {
-            let c_ings = Ingredient::get_ingredients_by_cocktail_id(db, &x.id).unwrap();
-            // TODO: fix me
-            // let mapped_ings = ingredients.iter().map(|x| IngredientData::from(x)).collect();
-            let mapped_ings = Ingredient::map_ingredients_to_data(c_ings);
-            
-            let instructions = Instruction::get_instructions_by_cocktail_id(db, &x.id).unwrap();
-            // TODO: fix me
-            // let mapped_ins = instructions.iter().map(|x| InstructionData::from(x.clone())).collect();
-            let mapped_ins = Instruction::map_instructions_to_data(instructions);
-
-            CocktailData { 
-                id: Some(x.id), 
-                name: x.name.clone(),
-                created_at: x.created_at,
-                updated_at: x.updated_at,
-                ingredients: mapped_ings, 
-                instructions: mapped_ins, 
-            }
-        }).collect();
-            
-        /*
-         * TODO: limit to those without add blocker or paid?
-         */
-        if c.len() < limit as usize {
-            // generate cocktails from chat gippity
-            let new_cocktails = Cocktail::ask_gpt_for_cocktails(&query.ingredients);
-            new_cocktails.iter().for_each(|c| {
-                // insert into db
-                let x = Cocktail::create_cocktail(db, c.clone());
-
-                match x {
-                    Ok(x) => {
-                        let result = Cocktail::get_cocktail_by_id(db, x.id);
-                        match result { 
-                            Some(cock) => {
-                                // add to cocktails vec
-                                cocktail_vec.push(cock);
-                            }
-                            None => {
-                                println!("[Cocktail][generate_cocktails] gippity cockatil not found after insert");
-                            }
-                        }
-                    },
-                    Err(err) => {
-                        println!("[Cocktail][generate_cocktails] Error inserting gippity cockatil: {:?}", err);
-                    }
+
+        let min_matches = query.min_matches.unwrap_or(1);
+        let min_coverage = query.min_coverage.unwrap_or(0.0);
+        if !(0.0..=1.0).contains(&min_coverage) {
+            return Err(ApiError::BadRequest("min_coverage must be between 0 and 1".to_string()));
+        }
+
+        let mut cursor = match &query.cursor {
+            Some(x) => GenerateCursor::decode(x, &query.ingredients, min_matches, min_coverage)?,
+            None => GenerateCursor::new(&query.ingredients, min_matches, min_coverage),
+        };
+
+        let mut conn = db.pool.get()?;
+        let requested = CanonicalIngredient::find_ids_by_names(&mut conn, &query.ingredients)?;
+
+        let job_id = match cursor.job_id {
+            Some(job_id) => job_id,
+            None => {
+                // one more than the page to know whether this is the last one
+                let mut matches = Ingredient::get_cocktail_matches(
+                    &mut conn,
+                    &requested,
+                    min_matches,
+                    min_coverage,
+                    cursor.after,
+                    limit as i64 + 1,
+                )?;
+                debug!(matches = matches.len(), "matched stored cocktails");
+
+                let exhausted = matches.len() <= limit as usize;
+                matches.truncate(limit as usize);
+                if let Some(last) = matches.last() {
+                    cursor.set_after(last);
+                }
+
+                let c_ids: Vec<Uuid> = matches.iter().map(|x| x.c_id).collect();
+                let cocktail_vec = Cocktail::get_cocktail_matches(&mut conn, &c_ids, &requested)?;
+
+                if !exhausted {
+                    return Ok(GenerateResult {
+                        cocktails: cocktail_vec,
+                        job_id: None,
+                        cursor: Some(cursor.encode()),
+                        quota_exceeded: false,
+                    });
+                }
+
+                // someone asked for the same ingredients already, no need to pay twice
+                if let Some(job) = GenerationJob::find_pending_job(&mut conn, &query.ingredients)? {
+                    cursor.job_id = Some(job.id);
+                    return Ok(GenerateResult {
+                        cocktails: cocktail_vec,
+                        job_id: Some(job.id),
+                        cursor: Some(cursor.encode()),
+                        quota_exceeded: false,
+                    });
                 }
+
+                // charged only for a job this request queued, and not at all if queueing fails
+                let queued = conn.transaction::<_, ApiError, _>(|conn| {
+                    match GenerationJob::create_job(conn, &query.ingredients)? {
+                        Some(job) => LlmUsage::record_generation(conn, subject, daily_llm_quota()).map(|_| job),
+                        None => GenerationJob::find_pending_job(conn, &query.ingredients)?
+                            .ok_or_else(|| ApiError::Conflict("A generation job for these ingredients was just queued".to_string())),
+                    }
+                });
+
+                return match queued {
+                    Ok(job) => {
+                        // generate cocktails from chat gippity in the background
+                        info!(job_id = %job.id, "queued generation job");
+                        cursor.job_id = Some(job.id);
+                        Ok(GenerateResult {
+                            cocktails: cocktail_vec,
+                            job_id: Some(job.id),
+                            cursor: Some(cursor.encode()),
+                            quota_exceeded: false,
+                        })
+                    },
+                    // the stored cocktails are still worth having
+                    Err(ApiError::TooManyRequests(_, _)) if !cocktail_vec.is_empty() => Ok(GenerateResult {
+                        cocktails: cocktail_vec,
+                        job_id: None,
+                        cursor: None,
+                        quota_exceeded: true,
+                    }),
+                    Err(err) => Err(err),
+                };
+            },
+        };
+
+        let job = GenerationJob::find_job(&mut conn, job_id)?;
+        let cursor = match job.status.as_str() {
+            // nothing new yet, the same cursor picks up where this left off
+            JOB_PENDING | JOB_RUNNING => Some(cursor.encode()),
+            JOB_DONE => {
+                // cocktails the LLM came up with that were already stored and match were
+                // returned with the stored matches
+                let unreturned: Vec<Uuid> = job.cocktail_ids.iter()
+                    .filter(|x| !cursor.returned.contains(x))
+                    .copied()
+                    .collect();
+                let mut cocktail_vec = Cocktail::get_cocktail_matches(&mut conn, &unreturned, &requested)?;
+                cocktail_vec.retain(|x| {
+                    x.cocktail.created_at >= job.created_at
+                        || (x.matched.len() as i64) < min_matches.max(1)
+                        || x.coverage < min_coverage
+                });
+
+                let more = cocktail_vec.len() > limit as usize;
+                cocktail_vec.truncate(limit as usize);
+                cursor.returned.extend(cocktail_vec.iter().filter_map(|x| x.cocktail.id));
+
+                let cursor = more.then(|| cursor.encode());
+                return Ok(GenerateResult { cocktails: cocktail_vec, job_id: Some(job.id), cursor, quota_exceeded: false });
+            },
+            _ => None,
+        };
+
+        Ok(GenerateResult { cocktails: vec![], job_id: Some(job.id), cursor, quota_exceeded: false })
+    }
+
+    /// The cocktails `c_ids` in that order as matches for the `requested` canonical ingredients,
+    /// leaving out any that have been deleted
+    fn get_cocktail_matches(conn: &mut PgConnection, c_ids: &[Uuid], requested: &[Uuid]) -> Result<Vec<CocktailMatch>, ApiError> {
+        let matches = Cocktail::get_cocktails_by_ids(conn, c_ids)?
+            .into_iter()
+            .map(|x| CocktailMatch::new(x, requested))
+            .collect();
+        Ok(matches)
+    }
+
+    /// Looks up a stored cocktail by name, ignoring case and whitespace
+    pub fn get_cocktail_by_name(conn: &mut PgConnection, c_name: &str) -> Result<Option<Cocktail>, ApiError> {
+        // the left hand side has to match the unique_name_normalized index expression
+        let cocktail = cocktails
+            .filter(normalize_cocktail_name(name).eq(normalize_cocktail_name(c_name)))
+            .first::<Cocktail>(conn)
+            .optional()?;
+        Ok(cocktail)
+    }
+
+    /// Finds a stored cocktail that is the same drink as `new_cocktail`, either by name or by a
+    /// near identical name with (nearly) the same ingredients, e.g. "Whisky Sour" and "Whiskey Sour"
+    pub fn find_duplicate(db: &Database, new_cocktail: &CocktailData) -> Result<Option<Cocktail>, ApiError> {
+        let mut conn = db.pool.get()?;
+        if let Some(existing) = Cocktail::get_cocktail_by_name(&mut conn, &new_cocktail.name)? {
+            return Ok(Some(existing));
+        }
+
+        let ing_names: Vec<String> = new_cocktail.ingredients.iter().map(|x| x.name.clone()).collect();
+        let c_ing_ids = CanonicalIngredient::find_ids_by_names(&mut conn, &ing_names)?;
+        let candidate_ids: Vec<Uuid> = Ingredient::get_cocktail_matches(&mut conn, &c_ing_ids, 1, 0.0, None, 20)?
+            .into_iter()
+            .map(|x| x.c_id)
+            .collect();
+
+        let duplicate = Cocktail::get_cocktails_by_ids(&mut conn, &candidate_ids)?
+            .into_iter()
+            .filter(|x| name_similarity(&x.name, &new_cocktail.name) >= DUPLICATE_NAME_SIMILARITY)
+            .find(|x| {
+                let existing_names: Vec<String> = x.ingredients.iter().map(|x| x.name.clone()).collect();
+                ingredient_overlap(&ing_names, &existing_names, DUPLICATE_NAME_SIMILARITY) >= DUPLICATE_INGREDIENT_OVERLAP
             });
+
+        match duplicate.and_then(|x| x.id) {
+            Some(c_id) => Ok(cocktails.find(c_id).get_result::<Cocktail>(&mut conn).optional()?),
+            None => Ok(None),
         }
+    }
 
-        Ok(cocktail_vec)
+    /// Inserts cocktails suggested by the LLM, returning the ids of the stored cocktails. When a
+    /// suggestion duplicates a stored cocktail the stored one is returned instead.
+    pub fn create_generated_cocktails(db: &Database, new_cocktails: Vec<CocktailData>) -> GeneratedCocktails {
+        let mut generated = GeneratedCocktails { ids: vec![], created: 0, duplicates: 0 };
+
+        new_cocktails.into_iter().for_each(|c| {
+            let existing = match Cocktail::find_duplicate(db, &c) {
+                Ok(existing) => existing,
+                Err(err) => {
+                    error!(name = %c.name, error = %err, "could not look up duplicates");
+                    return;
+                }
+            };
+
+            let c_id = match existing {
+                Some(x) => {
+                    info!(name = %c.name, existing = %x.name, id = %x.id, "suggested cocktail already exists");
+                    generated.duplicates += 1;
+                    Some(x.id)
+                },
+                None => {
+                    let c_name = c.name.clone();
+                    // insert into db
+                    match Cocktail::create_cocktail(db, c, None) {
+                        Ok(x) => {
+                            generated.created += 1;
+                            Some(x.id)
+                        },
+                        // inserted by someone else since we looked
+                        Err(ApiError::Conflict(_)) => {
+                            generated.duplicates += 1;
+                            db.pool.get()
+                                .ok()
+                                .and_then(|mut conn| Cocktail::get_cocktail_by_name(&mut conn, &c_name).ok().flatten())
+                                .map(|x| x.id)
+                        },
+                        Err(err) => {
+                            error!(name = %c_name, error = %err, "could not insert suggested cocktail");
+                            None
+                        }
+                    }
+                }
+            };
+
+            if let Some(c_id) = c_id {
+                if !generated.ids.contains(&c_id) {
+                    generated.ids.push(c_id);
+                }
+            }
+        });
 
+        generated
     }
 }
 
@@ -275,12 +744,15 @@ impl Cocktail {
 #[test]
 pub fn test_parse_message() {
     let test_message = "\n\n[[\"Whiskey Sour\",[[\"Whiskey\",\"2 ounces\"],[\"Lemon juice\",\"1 ounce\"],[\"Simple syrup\",\"1/2 ounce\"]],[\"Add whiskey, lemon juice and simple syrup to a shaker with ice.\",\"Shake and strain into a rocks glass with fresh ice.\",\"Garnish with a lemon wedge.\"]],\n[\"Tom Collins\",[[\"Gin\",\"2 ounces\"],[\"Lemon juice\",\"1 ounce\"],[\"Simple syrup\",\"1/2 ounce\"],[\"Club soda\",\"3 ounces\"]],[\"Fill a shaker with ice cubes.\",\"Add gin, lemon juice and simple syrup to the shaker.\",\"Shake and strain into a highball glass filled with ice.\",\"Top with club soda.\",\"Garnish with a lemon slice.\"]]]";
-    let result = CocktailData::parse_message(test_message);
+    let result = CocktailData::parse_message(test_message).unwrap();
     let actuall: Vec<CocktailData> = vec![
         CocktailData {
             id: None,
             created_at: None,
             updated_at: None,
+            abv: None,
+            standard_drinks: None,
+            created_by: None,
             name: "Whiskey Sour".to_owned(),
             ingredients: vec![
                 IngredientData {
@@ -290,6 +762,10 @@ pub fn test_parse_message() {
                     cocktail_id: None,
                     created_at: None, 
                     updated_at: None,
+                    canonical_ingredient_id: None,
+                    quantity: None,
+                    quantity_max: None,
+                    unit: None,
                 },
                 IngredientData {
                     name:"Lemon juice".to_owned(),
@@ -298,6 +774,10 @@ pub fn test_parse_message() {
                     cocktail_id: None,
                     created_at: None, 
                     updated_at: None,
+                    canonical_ingredient_id: None,
+                    quantity: None,
+                    quantity_max: None,
+                    unit: None,
                 },
                 IngredientData {
                     name: "Simple syrup".to_owned(),
@@ -306,6 +786,10 @@ pub fn test_parse_message() {
                     cocktail_id: None,
                     created_at: None, 
                     updated_at: None,
+                    canonical_ingredient_id: None,
+                    quantity: None,
+                    quantity_max: None,
+                    unit: None,
                 },
                 ],
                 instructions: vec![
@@ -339,6 +823,9 @@ pub fn test_parse_message() {
             id: None,
             created_at: None,
             updated_at: None,
+            abv: None,
+            standard_drinks: None,
+            created_by: None,
             name: "Tom Collins".to_owned(),
             ingredients: vec![
                 IngredientData {
@@ -348,6 +835,10 @@ pub fn test_parse_message() {
                     cocktail_id: None,
                     created_at: None, 
                     updated_at: None,
+                    canonical_ingredient_id: None,
+                    quantity: None,
+                    quantity_max: None,
+                    unit: None,
                 },
                 IngredientData {
                     name:"Lemon juice".to_owned(),
@@ -356,6 +847,10 @@ pub fn test_parse_message() {
                     cocktail_id: None,
                     created_at: None, 
                     updated_at: None,
+                    canonical_ingredient_id: None,
+                    quantity: None,
+                    quantity_max: None,
+                    unit: None,
                 },
                 IngredientData {
                     name: "Simple syrup".to_owned(),
@@ -364,6 +859,10 @@ pub fn test_parse_message() {
                     cocktail_id: None,
                     created_at: None, 
                     updated_at: None,
+                    canonical_ingredient_id: None,
+                    quantity: None,
+                    quantity_max: None,
+                    unit: None,
                 },
                 IngredientData {
                     name: "Club Soda".to_owned(),
@@ -372,6 +871,10 @@ pub fn test_parse_message() {
                     cocktail_id: None,
                     created_at: None, 
                     updated_at: None,
+                    canonical_ingredient_id: None,
+                    quantity: None,
+                    quantity_max: None,
+                    unit: None,
                 }
             ],
             instructions: vec![
@@ -477,3 +980,30 @@ pub fn test_parse_message() {
 
 
 
+
+
+#[test]
+pub fn test_cocktail_match_coverage() {
+    let mut cocktail = CocktailData::from((
+        "Gin Sour".to_owned(),
+        vec![
+            ("Gin".to_owned(), "2 ounces".to_owned()),
+            ("Lemon juice".to_owned(), "1 ounce".to_owned()),
+            ("Simple syrup".to_owned(), "1/2 ounce".to_owned()),
+            ("Egg white".to_owned(), "1".to_owned()),
+        ],
+        vec!["Shake everything with ice.".to_owned()],
+    ));
+    let c_ing_ids: Vec<Uuid> = cocktail.ingredients.iter().map(|_| Uuid::new_v4()).collect();
+    for (ing, c_ing_id) in cocktail.ingredients.iter_mut().zip(&c_ing_ids) {
+        ing.canonical_ingredient_id = Some(*c_ing_id);
+    }
+    // gin, lemon juice and an ingredient the cocktail doesn't use
+    let requested = vec![c_ing_ids[0], c_ing_ids[1], Uuid::new_v4()];
+
+    let result = CocktailMatch::new(cocktail, &requested);
+
+    assert_eq!(result.matched, vec!["Gin".to_owned(), "Lemon juice".to_owned()]);
+    assert_eq!(result.missing, vec!["Simple syrup".to_owned(), "Egg white".to_owned()]);
+    assert_eq!(result.coverage, 0.5);
+}
@@ -0,0 +1,102 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+
+use super::ingredients::IngredientMatch;
+
+/// Where a walk through generate results is up to. Stored matches are returned first in
+/// ranked order, once they run out the cocktails the LLM comes up with are returned.
+/// Handed to clients as an opaque string.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenerateCursor {
+    /// fingerprint of the request the cursor was issued for
+    #[serde(rename = "q")]
+    query: u64,
+    /// the last stored match returned as (cocktail_id, matched, total)
+    #[serde(rename = "a")]
+    pub after: Option<(Uuid, i64, i64)>,
+    /// the job asking the LLM for more, set once the stored matches are exhausted
+    #[serde(rename = "j")]
+    pub job_id: Option<Uuid>,
+    /// generated cocktails already returned
+    #[serde(rename = "r", default)]
+    pub returned: Vec<Uuid>,
+}
+
+impl GenerateCursor {
+    pub fn new(ingredients: &[String], min_matches: i64, min_coverage: f64) -> GenerateCursor {
+        GenerateCursor {
+            query: GenerateCursor::fingerprint(ingredients, min_matches, min_coverage),
+            after: None,
+            job_id: None,
+            returned: vec![],
+        }
+    }
+
+    /// Decodes a cursor, which has to have been issued for the same request
+    pub fn decode(cursor: &str, ingredients: &[String], min_matches: i64, min_coverage: f64) -> Result<GenerateCursor, ApiError> {
+        let invalid = || ApiError::BadRequest("Invalid cursor".to_string());
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let decoded: GenerateCursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+
+        if decoded.query != GenerateCursor::fingerprint(ingredients, min_matches, min_coverage) {
+            return Err(ApiError::BadRequest(
+                "The cursor belongs to a request for other ingredients".to_string()
+            ));
+        }
+        Ok(decoded)
+    }
+
+    pub fn encode(&self) -> String {
+        // serializing a struct of plain values can't fail
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn set_after(&mut self, last: &IngredientMatch) {
+        self.after = Some((last.c_id, last.matched, last.total));
+    }
+
+    /// The first 8 bytes of a sha256 of the request, which stays the same across builds so that
+    /// cursors survive upgrades
+    fn fingerprint(ingredients: &[String], min_matches: i64, min_coverage: f64) -> u64 {
+        let mut hasher = Sha256::new();
+        for ing in ingredients {
+            hasher.update(ing.trim().to_lowercase().as_bytes());
+            // so that ["ab", "c"] and ["a", "bc"] differ
+            hasher.update([0]);
+        }
+        hasher.update(min_matches.to_be_bytes());
+        hasher.update(min_coverage.to_bits().to_be_bytes());
+
+        let mut prefix = [0; 8];
+        prefix.copy_from_slice(&hasher.finalize()[..8]);
+        u64::from_be_bytes(prefix)
+    }
+}
+
+
+#[test]
+pub fn test_generate_cursor() {
+    let ings = vec!["Gin".to_owned(), "lime juice".to_owned()];
+    let mut cursor = GenerateCursor::new(&ings, 1, 0.0);
+    cursor.after = Some((Uuid::new_v4(), 2, 3));
+    cursor.returned.push(Uuid::new_v4());
+
+    let encoded = cursor.encode();
+    assert_eq!(GenerateCursor::decode(&encoded, &[" gin".to_owned(), "Lime Juice".to_owned()], 1, 0.0).unwrap(), cursor);
+
+    assert!(GenerateCursor::decode(&encoded, &ings, 2, 0.0).is_err());
+    assert!(GenerateCursor::decode(&encoded, &ings[..1], 1, 0.0).is_err());
+    assert!(GenerateCursor::decode("not a cursor", &ings, 1, 0.0).is_err());
+
+    // cursors issued by earlier builds have to stay valid
+    assert_eq!(GenerateCursor::fingerprint(&ings, 1, 0.0), 6962140658681977255);
+    assert_ne!(
+        GenerateCursor::fingerprint(&["ab".to_owned(), "c".to_owned()], 1, 0.0),
+        GenerateCursor::fingerprint(&["a".to_owned(), "bc".to_owned()], 1, 0.0),
+    );
+}
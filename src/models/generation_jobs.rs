@@ -0,0 +1,244 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, Selectable, Identifiable};
+use serde::{Deserialize, Serialize};
+use tracing::{info, info_span, warn};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::llm::CocktailSuggester;
+use crate::repository::schema::generation_jobs::dsl::*;
+use crate::repository::database::Database;
+use crate::telemetry::llm_metrics;
+
+use super::canonical_ingredients::CanonicalIngredient;
+use super::cocktails::{Cocktail, CocktailMatch};
+
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_DONE: &str = "done";
+pub const JOB_FAILED: &str = "failed";
+
+/// Running jobs not heard from for this long are taken for abandoned (the worker died)
+const STALE_AFTER_MINUTES: i64 = 10;
+/// How often a worker tells that it's still on its job
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A request for the LLM to come up with cocktails, worked off in the background
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = crate::repository::schema::generation_jobs)]
+pub struct GenerationJob {
+    pub id: Uuid,
+    pub ingredients: Vec<String>,
+    pub status: String,
+    pub error: Option<String>,
+    pub cocktail_ids: Vec<Uuid>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    /// lease of the worker running the job, changes when it is claimed again
+    #[serde(skip_serializing)]
+    pub claimed_by: Option<Uuid>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GenerationJobData {
+    pub id: Uuid,
+    pub status: String,
+    pub ingredients: Vec<String>,
+    pub error: Option<String>,
+    pub cocktails: Vec<CocktailMatch>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl GenerationJob {
+    /// Queues a job for `ings`. None when one is already pending for them, see `find_pending_job`.
+    pub fn create_job(conn: &mut PgConnection, ings: &[String]) -> Result<Option<GenerationJob>, ApiError> {
+        let job = GenerationJob {
+            id: Uuid::new_v4(),
+            ingredients: job_ingredients(ings),
+            status: JOB_PENDING.to_string(),
+            error: None,
+            cocktail_ids: vec![],
+            created_at: Some(Utc::now().naive_utc()),
+            updated_at: Some(Utc::now().naive_utc()),
+            claimed_by: None,
+        };
+
+        // generation_jobs_pending_ingredients_idx allows one pending job per ingredient set
+        let created = diesel::insert_into(generation_jobs)
+            .values(&job)
+            .on_conflict_do_nothing()
+            .get_result::<GenerationJob>(conn)
+            .optional()?;
+        Ok(created)
+    }
+
+    /// The job waiting to be run for `ings`, if there is one
+    pub fn find_pending_job(conn: &mut PgConnection, ings: &[String]) -> Result<Option<GenerationJob>, ApiError> {
+        let job = generation_jobs
+            .filter(status.eq(JOB_PENDING))
+            .filter(ingredients.eq(job_ingredients(ings)))
+            .first::<GenerationJob>(conn)
+            .optional()?;
+        Ok(job)
+    }
+
+    pub fn find_job(conn: &mut PgConnection, job_id: Uuid) -> Result<GenerationJob, ApiError> {
+        let job = generation_jobs.find(job_id)
+            .get_result::<GenerationJob>(conn)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound("Generation job not found".to_string()))?;
+        Ok(job)
+    }
+
+    pub fn get_job_by_id(db: &Database, job_id: Uuid) -> Result<GenerationJobData, ApiError> {
+        let mut conn = db.pool.get()?;
+        let job = GenerationJob::find_job(&mut conn, job_id)?;
+
+        let requested = CanonicalIngredient::find_ids_by_names(&mut conn, &job.ingredients)?;
+        // cocktails deleted since the job ran are left out
+        let job_cocktails = Cocktail::get_cocktails_by_ids(&mut conn, &job.cocktail_ids)?
+            .into_iter()
+            .map(|x| CocktailMatch::new(x, &requested))
+            .collect();
+
+        Ok(GenerationJobData {
+            id: job.id,
+            status: job.status,
+            ingredients: job.ingredients,
+            error: job.error,
+            cocktails: job_cocktails,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+        })
+    }
+
+    /// Marks the oldest pending job as running under a new lease and returns it. Running jobs
+    /// without a heartbeat for too long (e.g. the worker died) are picked up again.
+    pub fn claim_next_job(db: &Database) -> Result<Option<GenerationJob>, ApiError> {
+        let mut conn = db.pool.get()?;
+        // timestamps are stored in utc by the row triggers
+        let stale_before = Utc::now().naive_utc() - chrono::Duration::minutes(STALE_AFTER_MINUTES);
+
+        let job = conn.transaction::<_, ApiError, _>(|conn| {
+            let next = generation_jobs
+                .filter(status.eq(JOB_PENDING)
+                    .or(status.eq(JOB_RUNNING).and(updated_at.lt(stale_before))))
+                .order(created_at.asc())
+                .for_update()
+                .skip_locked()
+                .first::<GenerationJob>(conn)
+                .optional()?;
+
+            match next {
+                Some(job) => {
+                    let job = diesel::update(generation_jobs.find(job.id))
+                        .set((status.eq(JOB_RUNNING), claimed_by.eq(Uuid::new_v4())))
+                        .get_result::<GenerationJob>(conn)?;
+                    Ok(Some(job))
+                },
+                None => Ok(None),
+            }
+        })?;
+
+        Ok(job)
+    }
+
+    /// Asks the LLM for cocktails for claimed `job` and stores the ones that could be inserted,
+    /// unless another worker has claimed the job in the meantime
+    pub fn run_job(db: &Database, suggester: &dyn CocktailSuggester, job: &GenerationJob) -> Result<(), ApiError> {
+        let lease = job.claimed_by
+            .ok_or_else(|| ApiError::Internal("Generation job was run without claiming it".to_string()))?;
+
+        let (done, heartbeat) = mpsc::channel::<()>();
+        let result = thread::scope(|scope| {
+            scope.spawn(move || GenerationJob::keep_claimed(db, job.id, lease, heartbeat));
+            let result = suggester.suggest_cocktails(&job.ingredients);
+            drop(done);
+            result
+        });
+
+        let claimed = generation_jobs.find(job.id)
+            .select(claimed_by)
+            .get_result::<Option<Uuid>>(&mut db.pool.get()?)
+            .optional()?;
+        if claimed != Some(Some(lease)) {
+            warn!(job_id = %job.id, "generation job was claimed by another worker, dropping the result");
+            return Ok(());
+        }
+
+        let this_job = generation_jobs.filter(id.eq(job.id)).filter(claimed_by.eq(lease));
+        match result {
+            Ok(new_cocktails) => {
+                let generated = Cocktail::create_generated_cocktails(db, new_cocktails);
+                info!(created = generated.created, duplicates = generated.duplicates, "generation job done");
+                llm_metrics().record_cocktails(suggester.provider(), suggester.model(), generated.created, generated.duplicates);
+                diesel::update(this_job)
+                    .set((status.eq(JOB_DONE), cocktail_ids.eq(generated.ids)))
+                    .execute(&mut db.pool.get()?)?;
+            },
+            Err(err) => {
+                warn!(job_id = %job.id, error = %err, "generation job failed");
+                diesel::update(this_job)
+                    .set((status.eq(JOB_FAILED), error.eq(err.to_string())))
+                    .execute(&mut db.pool.get()?)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Refreshes `updated_at` of the job while it runs, so that it isn't taken for abandoned,
+    /// until `stop` is dropped
+    fn keep_claimed(db: &Database, job_id: Uuid, lease: Uuid, stop: Receiver<()>) {
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(HEARTBEAT_INTERVAL) {
+            let touched = db.pool.get()
+                .map_err(ApiError::from)
+                .and_then(|mut conn| {
+                    diesel::update(generation_jobs.filter(id.eq(job_id)).filter(claimed_by.eq(lease)))
+                        .set(updated_at.eq(Utc::now().naive_utc()))
+                        .execute(&mut conn)
+                        .map_err(ApiError::from)
+                });
+            if let Err(err) = touched {
+                warn!(job_id = %job_id, error = %err, "could not refresh the generation job");
+            }
+        }
+    }
+
+    /// Claims and runs the next job. Returns false when there was nothing to do.
+    pub fn run_next_job(db: &Database, suggester: &dyn CocktailSuggester) -> Result<bool, ApiError> {
+        match GenerationJob::claim_next_job(db)? {
+            Some(job) => {
+                // the request that queued the job logged its id
+                let _span = info_span!("generation_job", job_id = %job.id).entered();
+                GenerationJob::run_job(db, suggester, &job)?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+}
+
+/// Ingredients as stored on jobs, trimmed and lowercased like the generate cursor's fingerprint,
+/// sorted and without repeats so that the same set is equal
+fn job_ingredients(ings: &[String]) -> Vec<String> {
+    let mut ings: Vec<String> = ings.iter().map(|x| x.trim().to_lowercase()).collect();
+    ings.sort();
+    ings.dedup();
+    ings
+}
+
+
+#[test]
+pub fn test_job_ingredients() {
+    let ings = ["lime".to_owned(), "gin".to_owned(), "lime".to_owned()];
+    assert_eq!(job_ingredients(&ings), vec!["gin".to_owned(), "lime".to_owned()]);
+    let ings = ["Lime ".to_owned(), "gin".to_owned(), " GIN".to_owned()];
+    assert_eq!(job_ingredients(&ings), vec!["gin".to_owned(), "lime".to_owned()]);
+}
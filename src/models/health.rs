@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use diesel::prelude::*;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Serialize;
+
+use crate::error::ApiError;
+use crate::llm::CocktailSuggester;
+use crate::repository::database::Database;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// How long readiness waits for a connection, rather than the pool's connection timeout
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+/// How one thing readiness depends on is doing
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Component {
+    pub status: Status,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Component {
+    fn checked(started: Instant, result: Result<(), ApiError>) -> Component {
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(()) => Component { status: Status::Up, latency_ms, error: None },
+            Err(err) => Component { status: Status::Down, latency_ms, error: Some(err.to_string()) },
+        }
+    }
+}
+
+/// What `/health/ready` answers, ready when every component is up
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    pub components: BTreeMap<&'static str, Component>,
+}
+
+impl Readiness {
+    /// Checks that a connection can be taken from the pool, that every migration has run and,
+    /// given a suggester, that the LLM provider answers. Blocks for up to a few seconds.
+    pub fn check(db: &Database, llm: Option<&dyn CocktailSuggester>) -> Readiness {
+        let mut components = BTreeMap::new();
+
+        let started = Instant::now();
+        let conn = db.pool.get_timeout(CONNECTION_TIMEOUT)
+            .map_err(ApiError::from)
+            .and_then(|mut conn| {
+                diesel::sql_query("SELECT 1").execute(&mut conn)?;
+                Ok(conn)
+            });
+        let (database, mut conn) = match conn {
+            Ok(conn) => (Ok(()), Some(conn)),
+            Err(err) => (Err(err), None),
+        };
+        components.insert("database", Component::checked(started, database));
+
+        let started = Instant::now();
+        let migrations = match conn.as_mut() {
+            Some(conn) => Readiness::check_migrations(conn),
+            None => Err(ApiError::ServiceUnavailable("The database is unreachable".to_string())),
+        };
+        components.insert("migrations", Component::checked(started, migrations));
+
+        if let Some(llm) = llm {
+            let started = Instant::now();
+            components.insert("llm", Component::checked(started, llm.ping()));
+        }
+
+        let ready = components.values().all(|x| x.status == Status::Up);
+        Readiness { ready, components }
+    }
+
+    fn check_migrations(conn: &mut PgConnection) -> Result<(), ApiError> {
+        let pending = conn.pending_migrations(MIGRATIONS)
+            .map_err(|err| ApiError::ServiceUnavailable(format!("Could not check the migrations: {}", err)))?;
+
+        match pending.as_slice() {
+            [] => Ok(()),
+            pending => Err(ApiError::ServiceUnavailable(format!(
+                "{} pending migrations: {}",
+                pending.len(),
+                pending.iter().map(|x| x.name().version().to_string()).collect::<Vec<String>>().join(", "),
+            ))),
+        }
+    }
+}
+
+
+#[test]
+pub fn test_embedded_migrations() {
+    use diesel::pg::Pg;
+    use diesel::migration::MigrationSource;
+
+    let versions = MigrationSource::<Pg>::migrations(&MIGRATIONS).unwrap()
+        .iter()
+        .map(|x| x.name().version().to_string())
+        .collect::<Vec<String>>();
+    assert!(versions.contains(&"00000000000000".to_string()));
+    assert!(versions.contains(&"20230701100000".to_string()));
+}
@@ -1,16 +1,21 @@
-use diesel::dsl::count;
 use diesel::expression::ValidGrouping;
 // use diesel::pg::Pg;
-use diesel::result::Error;
-use diesel::{Queryable, Insertable, RunQueryDsl, Selectable, Identifiable};
+use diesel::sql_types::{Array, BigInt, Double, Nullable};
+use diesel::{Associations, Queryable, QueryableByName, Insertable, RunQueryDsl, Selectable, Identifiable};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::repository::schema::ingredients::dsl::*;
+use crate::error::ApiError;
 use crate::repository::database::Database;
 
-#[derive(Queryable, Selectable, Identifiable, Debug, Deserialize, Insertable, ValidGrouping)]
+use super::canonical_ingredients::CanonicalIngredient;
+use super::cocktails::Cocktail;
+use super::measurements::{Measurement, Unit, UnitSystem};
+
+#[derive(Queryable, Selectable, Identifiable, Associations, Debug, Deserialize, Insertable, ValidGrouping)]
+#[diesel(belongs_to(Cocktail))]
 #[diesel(table_name = crate::repository::schema::ingredients)]
 pub struct Ingredient {
     #[serde(default)]
@@ -20,6 +25,10 @@ pub struct Ingredient {
     pub cocktail_id: Uuid,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
+    pub canonical_ingredient_id: Option<Uuid>,
+    pub quantity: Option<f64>,
+    pub quantity_max: Option<f64>,
+    pub unit: Option<String>,
 }
 
 
@@ -31,6 +40,23 @@ pub struct IngredientData {
     pub cocktail_id: Option<Uuid>,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
+    pub canonical_ingredient_id: Option<Uuid>,
+    pub quantity: Option<f64>,
+    pub quantity_max: Option<f64>,
+    pub unit: Option<String>,
+}
+
+/// How many of a cocktail's ingredients were found in a list of canonical ingredients
+#[derive(QueryableByName, Debug, Clone)]
+pub struct IngredientMatch {
+    #[diesel(sql_type = diesel::sql_types::Uuid, column_name = cocktail_id)]
+    pub c_id: Uuid,
+    #[diesel(sql_type = BigInt)]
+    pub matched: i64,
+    #[diesel(sql_type = BigInt)]
+    pub total: i64,
+    #[diesel(sql_type = Double)]
+    pub coverage: f64,
 }
 
 impl From<(String, String)> for IngredientData {
@@ -42,6 +68,10 @@ impl From<(String, String)> for IngredientData {
             cocktail_id: None,
             updated_at: None,
             created_at: None,
+            canonical_ingredient_id: None,
+            quantity: None,
+            quantity_max: None,
+            unit: None,
         }
     }
 }
@@ -55,6 +85,10 @@ impl From<Ingredient> for IngredientData {
            cocktail_id: Some(ingredient.cocktail_id),
            created_at: ingredient.created_at,
            updated_at: ingredient.updated_at,
+           canonical_ingredient_id: ingredient.canonical_ingredient_id,
+           quantity: ingredient.quantity,
+           quantity_max: ingredient.quantity_max,
+           unit: ingredient.unit,
         }
     }
 }
@@ -68,28 +102,153 @@ impl From<IngredientData> for Ingredient {
            cocktail_id: ins.cocktail_id.unwrap(),
            created_at: ins.created_at,
            updated_at: ins.updated_at,
+           canonical_ingredient_id: ins.canonical_ingredient_id,
+           quantity: ins.quantity,
+           quantity_max: ins.quantity_max,
+           unit: ins.unit,
         }
 
     }
 }
 
+impl IngredientData {
+    /// Renders the amount in `system`, leaving units that don't convert alone
+    pub fn convert_units(&mut self, system: UnitSystem) {
+        let stored = Measurement {
+            quantity: self.quantity,
+            quantity_max: self.quantity_max,
+            unit: self.unit.as_deref().and_then(|x| x.parse::<Unit>().ok()),
+        };
+        if stored.quantity.is_none() {
+            return;
+        }
+
+        let converted = stored.convert(system);
+        if converted == stored {
+            return;
+        }
+
+        self.quantity = converted.quantity;
+        self.quantity_max = converted.quantity_max;
+        self.unit = converted.unit.map(|x| x.as_str().to_string());
+        if let Some(rendered) = converted.render() {
+            self.measurement = rendered;
+        }
+    }
+}
+
 impl Ingredient { 
-    pub fn create_ingredient(db: &Database, ingredient: Ingredient) {
-        diesel::insert_into(ingredients)
-            .values(&ingredient)
-            .execute(&mut db.pool.get().unwrap())
-            .expect("Error inserting ingredient");
+    pub fn create_ingredient(db: &Database, ingredient: Ingredient) -> Result<usize, ApiError> {
+        let mut conn = db.pool.get()?;
+        Ingredient::create_ingredients(&mut conn, vec![ingredient])
+    }
+
+    /// Fills in quantity, quantity_max and unit from the measurement text
+    pub fn parse_measurement(&mut self) {
+        let parsed = Measurement::parse(&self.measurement);
+        self.quantity = parsed.quantity;
+        self.quantity_max = parsed.quantity_max;
+        self.unit = parsed.unit.map(|x| x.as_str().to_string());
     }
 
-    pub fn create_ingredients(db: &Database, ing_vec: Vec<Ingredient>) -> Result<usize, Error> {
-        diesel::insert_into(ingredients)
+    /// Inserts `ing_vec`, linking every ingredient to the canonical ingredient for its name and
+    /// parsing its measurement
+    pub fn create_ingredients(conn: &mut PgConnection, mut ing_vec: Vec<Ingredient>) -> Result<usize, ApiError> {
+        for ing in ing_vec.iter_mut() {
+            ing.canonical_ingredient_id = Some(CanonicalIngredient::resolve(conn, &ing.name)?);
+            ing.parse_measurement();
+        }
+
+        let count = diesel::insert_into(ingredients)
             .values(&ing_vec)
-            .execute(&mut db.pool.get().unwrap())
+            .execute(conn)?;
+        Ok(count)
     }
 
-    pub fn get_ingredients_by_cocktail_id(db: &Database, c_id: &Uuid) -> Result<Vec<Ingredient>, Error> {
-        ingredients.filter(cocktail_id.eq(c_id))
-            .get_results::<Ingredient>(&mut db.pool.get().unwrap())
+    /// Reconciles the stored ingredients of a cocktail with `ing_data`. Ingredients with a
+    /// known id are updated, ingredients without an id are inserted and the rest are deleted.
+    pub fn sync_ingredients(conn: &mut PgConnection, c_id: &Uuid, ing_data: Vec<IngredientData>) -> Result<(), ApiError> {
+        let existing = ingredients.filter(cocktail_id.eq(c_id))
+            .select(id)
+            .get_results::<Uuid>(conn)?;
+
+        let mut kept: Vec<Uuid> = vec![];
+        let mut new_ings: Vec<Ingredient> = vec![];
+
+        for ing in ing_data {
+            match ing.id {
+                Some(i_id) if existing.contains(&i_id) => {
+                    let c_ing_id = CanonicalIngredient::resolve(conn, &ing.name)?;
+                    let parsed = Measurement::parse(&ing.measurement);
+                    diesel::update(ingredients.find(i_id))
+                        .set((
+                            name.eq(&ing.name),
+                            measurement.eq(&ing.measurement),
+                            canonical_ingredient_id.eq(c_ing_id),
+                            quantity.eq(parsed.quantity),
+                            quantity_max.eq(parsed.quantity_max),
+                            unit.eq(parsed.unit.map(|x| x.as_str())),
+                        ))
+                        .execute(conn)?;
+                    kept.push(i_id);
+                },
+                Some(i_id) => {
+                    return Err(ApiError::BadRequest(
+                        format!("Ingredient {} does not belong to cocktail {}", i_id, c_id)
+                    ));
+                },
+                None => new_ings.push(Ingredient {
+                    cocktail_id: *c_id,
+                    ..Ingredient::map_data_to_ingredient(&ing, c_id)
+                }),
+            }
+        }
+
+        diesel::delete(ingredients.filter(cocktail_id.eq(c_id)).filter(id.ne_all(&kept)))
+            .execute(conn)?;
+
+        if !new_ings.is_empty() {
+            Ingredient::create_ingredients(conn, new_ings)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses the measurements of ingredients stored before quantities were, returns how many
+    /// could be parsed
+    pub fn backfill_measurements(db: &Database) -> Result<usize, ApiError> {
+        let mut conn = db.pool.get()?;
+        let unparsed = ingredients.filter(quantity.is_null().and(unit.is_null()))
+            .get_results::<Ingredient>(&mut conn)?;
+
+        let mut count = 0;
+        for mut ing in unparsed {
+            ing.parse_measurement();
+            if ing.quantity.is_none() && ing.unit.is_none() {
+                continue;
+            }
+
+            diesel::update(ingredients.find(ing.id))
+                .set((quantity.eq(ing.quantity), quantity_max.eq(ing.quantity_max), unit.eq(&ing.unit)))
+                .execute(&mut conn)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    pub fn get_ingredients_by_cocktail_id(db: &Database, c_id: &Uuid) -> Result<Vec<Ingredient>, ApiError> {
+        let results = ingredients.filter(cocktail_id.eq(c_id))
+            .get_results::<Ingredient>(&mut db.pool.get()?)?;
+        Ok(results)
+    }
+
+    /// The ingredients of each of `list`, in the same order, in one query
+    pub fn get_ingredients_by_cocktails(conn: &mut PgConnection, list: &[Cocktail]) -> Result<Vec<Vec<Ingredient>>, ApiError> {
+        let results = Ingredient::belonging_to(list)
+            .get_results::<Ingredient>(conn)?
+            .grouped_by(list);
+        Ok(results)
     }
 
     // TODO: update to use From trait
@@ -101,11 +260,15 @@ impl Ingredient {
            cocktail_id: Some(ins.cocktail_id),
            created_at: ins.created_at,
            updated_at: ins.updated_at,
+           canonical_ingredient_id: ins.canonical_ingredient_id,
+           quantity: ins.quantity,
+           quantity_max: ins.quantity_max,
+           unit: ins.unit.clone(),
        }
     }
 
     pub fn map_ingredients_to_data(ins: Vec<Ingredient>) -> Vec<IngredientData> {
-       ins.iter().map(|x| Ingredient::map_ingredient_to_data(x)).collect() 
+       ins.iter().map(Ingredient::map_ingredient_to_data).collect() 
     }
 
     // TODO: update to use From trait
@@ -114,9 +277,13 @@ impl Ingredient {
            id: ins.id.unwrap_or(uuid::Uuid::new_v4()),
            name: ins.name.clone(),
            measurement: ins.measurement.clone(),
-           cocktail_id: ins.cocktail_id.unwrap_or(c_id.clone()),
+           cocktail_id: ins.cocktail_id.unwrap_or(*c_id),
            created_at: ins.created_at,
            updated_at: ins.updated_at,
+           canonical_ingredient_id: ins.canonical_ingredient_id,
+           quantity: ins.quantity,
+           quantity_max: ins.quantity_max,
+           unit: ins.unit.clone(),
        }
     }
 
@@ -125,29 +292,51 @@ impl Ingredient {
     }
 
 
-    // TODO: pass in the desired min ingredient count 
-    pub fn get_ingredients_by_names(db: &Database, ns: &Vec<String>) -> Result<Vec<Ingredient>, Error> {
-        // 2. order by cocktail_id
-        // 3. order by count and percentage of ingredients in cocktail (if 4 out of 5 ingredients are in
-        //    ingredient list passed to genereate endpoint add cocktail to return list of cocktails)
-        // 4. If percentage of cocktail is completed is less than 25% or 1 ingredinet out of
-        let query = ingredients.filter(name.eq_any(ns))
-            .select(cocktail_id)
-            .group_by(cocktail_id)
-            // so that the user could limit the min number of ingredients 
-            // in the suggested cocktails
-            // TODO: update this to be a variable that is passed in
-            .having(count(cocktail_id).ge(1))
-            // is this necessary if we don't need to use the count?
-            // TODO: remove if not needed
-            .order(count(cocktail_id).desc());
+    /// Ranks cocktails by how many of their ingredients are in `c_ing_ids` (canonical ingredient
+    /// ids), best coverage first. Only cocktails with at least `min_matches` matching ingredients
+    /// and a coverage of at least `min_coverage` (0.0 - 1.0) are returned, starting after the
+    /// `after` match as (cocktail_id, matched, total).
+    pub fn get_cocktail_matches(
+        conn: &mut PgConnection,
+        c_ing_ids: &[Uuid],
+        min_matches: i64,
+        min_coverage: f64,
+        after: Option<(Uuid, i64, i64)>,
+        limit: i64,
+    ) -> Result<Vec<IngredientMatch>, ApiError> {
+        let (after_id, after_matched, after_total) = match after {
+            Some((c_id, matched, total)) => (Some(c_id), matched, total),
+            None => (None, 0, 1),
+        };
+        // coverages are compared as matched * total cross products so that the position
+        // doesn't depend on floats surviving the round trip through a cursor
+        let query = diesel::sql_query(
+            "SELECT cocktail_id, matched, total, coverage FROM ( \
+                SELECT cocktail_id, \
+                       COUNT(*) FILTER (WHERE canonical_ingredient_id = ANY($1)) AS matched, \
+                       COUNT(*) AS total, \
+                       (COUNT(*) FILTER (WHERE canonical_ingredient_id = ANY($1)))::float8 / COUNT(*) AS coverage \
+                FROM ingredients \
+                GROUP BY cocktail_id \
+             ) m \
+             WHERE matched >= $2 AND coverage >= $3 \
+               AND ($4::uuid IS NULL \
+                    OR matched * $6 < $5 * total \
+                    OR (matched * $6 = $5 * total AND (matched < $5 OR (matched = $5 AND cocktail_id > $4)))) \
+             ORDER BY coverage DESC, matched DESC, cocktail_id \
+             LIMIT $7"
+        )
+            .bind::<Array<diesel::sql_types::Uuid>, _>(c_ing_ids)
+            // a cocktail needs at least one matching ingredient to be suggested
+            .bind::<BigInt, _>(min_matches.max(1))
+            .bind::<Double, _>(min_coverage)
+            .bind::<Nullable<diesel::sql_types::Uuid>, _>(after_id)
+            .bind::<BigInt, _>(after_matched)
+            .bind::<BigInt, _>(after_total)
+            .bind::<BigInt, _>(limit);
 
         // println!("{}", debug_query::<Pg, _>(&query));
-        let ids = query.get_results::<Uuid>(&mut db.pool.get().unwrap())?;
-
-        // will this preserve the order of the last query? Probabbly not
-        // TODO: look into sorting the result
-        ingredients.filter(cocktail_id.eq_any(ids))
-            .get_results::<Ingredient>(&mut db.pool.get().unwrap())
+        let matches = query.get_results::<IngredientMatch>(conn)?;
+        Ok(matches)
     }
 }
@@ -1,13 +1,16 @@
-use diesel::result::Error;
-use diesel::{Queryable, RunQueryDsl, Selectable, Identifiable};
+use diesel::{Associations, Queryable, RunQueryDsl, Selectable, Identifiable};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::repository::schema::instructions::dsl::*;
+use crate::error::ApiError;
 use crate::repository::database::Database;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Insertable, AsChangeset, Selectable, Identifiable)]
+use super::cocktails::Cocktail;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Insertable, AsChangeset, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Cocktail))]
 #[diesel(table_name = crate::repository::schema::instructions)]
 pub struct Instruction {
     pub id: Uuid,
@@ -55,23 +58,77 @@ impl From<Instruction> for InstructionData {
 }
 
 impl Instruction { 
-    pub fn create_instruction(db: &Database, ins: Instruction) {
-        diesel::insert_into(instructions)
+    pub fn create_instruction(db: &Database, ins: Instruction) -> Result<usize, ApiError> {
+        let count = diesel::insert_into(instructions)
             .values(&ins)
-            .execute(&mut db.pool.get().unwrap())
-            .expect("Error inserting ingredient");
+            .execute(&mut db.pool.get()?)?;
+        Ok(count)
     }
 
-    pub fn create_instructions(db: &Database, ins_vec: Vec<Instruction>) -> Result<usize, Error> {
-        diesel::insert_into(instructions)
+    pub fn create_instructions(conn: &mut PgConnection, ins_vec: Vec<Instruction>) -> Result<usize, ApiError> {
+        let count = diesel::insert_into(instructions)
             .values(&ins_vec)
-            .execute(&mut db.pool.get().unwrap())
+            .execute(conn)?;
+        Ok(count)
+    }
+
+    /// Reconciles the stored instructions of a cocktail with `ins_data`. Steps are renumbered
+    /// to follow the order of `ins_data`, instructions with a known id are updated, instructions
+    /// without an id are inserted and the rest are deleted.
+    pub fn sync_instructions(conn: &mut PgConnection, c_id: &Uuid, ins_data: Vec<InstructionData>) -> Result<(), ApiError> {
+        let existing = instructions.filter(cocktail_id.eq(c_id))
+            .select(id)
+            .get_results::<Uuid>(conn)?;
+
+        let mut kept: Vec<Uuid> = vec![];
+        let mut new_ins: Vec<Instruction> = vec![];
+
+        for (i, ins) in ins_data.into_iter().enumerate() {
+            let ins_step = i as i16;
+            match ins.id {
+                Some(i_id) if existing.contains(&i_id) => {
+                    diesel::update(instructions.find(i_id))
+                        .set((instruction.eq(&ins.instruction), step.eq(ins_step)))
+                        .execute(conn)?;
+                    kept.push(i_id);
+                },
+                Some(i_id) => {
+                    return Err(ApiError::BadRequest(
+                        format!("Instruction {} does not belong to cocktail {}", i_id, c_id)
+                    ));
+                },
+                None => new_ins.push(Instruction {
+                    step: ins_step,
+                    cocktail_id: *c_id,
+                    ..Instruction::map_data_to_instruction(&ins, c_id)
+                }),
+            }
+        }
+
+        diesel::delete(instructions.filter(cocktail_id.eq(c_id)).filter(id.ne_all(&kept)))
+            .execute(conn)?;
+
+        if !new_ins.is_empty() {
+            Instruction::create_instructions(conn, new_ins)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_instructions_by_cocktail_id(db: &Database, c_id: &Uuid) -> Result<Vec<Instruction>, ApiError> {
+        let results = instructions.filter(cocktail_id.eq(c_id))
+            .order_by(step)
+            .get_results::<Instruction>(&mut db.pool.get()?)?;
+        Ok(results)
     }
 
-    pub fn get_instructions_by_cocktail_id(db: &Database, c_id: &Uuid) -> Result<Vec<Instruction>, Error> {
-        instructions.filter(cocktail_id.eq(c_id))
+    /// The instructions of each of `list` in step order, in the same order as `list`, in one query
+    pub fn get_instructions_by_cocktails(conn: &mut PgConnection, list: &[Cocktail]) -> Result<Vec<Vec<Instruction>>, ApiError> {
+        let results = Instruction::belonging_to(list)
             .order_by(step)
-            .get_results::<Instruction>(&mut db.pool.get().unwrap())
+            .get_results::<Instruction>(conn)?
+            .grouped_by(list);
+        Ok(results)
     }
 
     // TODO: update to use From trait
@@ -87,7 +144,7 @@ impl Instruction {
     }
 
     pub fn map_instructions_to_data(ins: Vec<Instruction>) -> Vec<InstructionData> {
-       ins.iter().map(|x| Instruction::map_instruction_to_data(x)).collect() 
+       ins.iter().map(Instruction::map_instruction_to_data).collect() 
     }
 
     // TODO: update to use From trait
@@ -96,7 +153,7 @@ impl Instruction {
            id: ins.id.unwrap_or(uuid::Uuid::new_v4()),
            instruction: ins.instruction.clone(),
            step: ins.step,
-           cocktail_id: ins.cocktail_id.unwrap_or(c_id.clone()),
+           cocktail_id: ins.cocktail_id.unwrap_or(*c_id),
            created_at: ins.created_at,
            updated_at: ins.updated_at,
        }
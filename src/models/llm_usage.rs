@@ -0,0 +1,53 @@
+use chrono::{NaiveTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Date, Integer, Varchar};
+use diesel::QueryableByName;
+
+use crate::config;
+use crate::error::ApiError;
+
+/// LLM generations a key, user or ip may queue a day, `llm.daily_quota`
+pub fn daily_llm_quota() -> i32 {
+    config::get().llm.daily_quota
+}
+
+#[derive(QueryableByName, Debug)]
+struct Usage {
+    #[diesel(sql_type = Integer)]
+    generations: i32,
+}
+
+pub struct LlmUsage;
+
+impl LlmUsage {
+    /// Counts a generation against today's (UTC) quota of `subject`, see `rate_limit::subject`.
+    /// Once it is used up nothing is counted and the error says how long until midnight.
+    pub fn record_generation(conn: &mut PgConnection, subject: &str, quota: i32) -> Result<i32, ApiError> {
+        let now = Utc::now().naive_utc();
+        let used_up = || {
+            let tomorrow = now.date().succ_opt().unwrap_or(now.date()).and_time(NaiveTime::MIN);
+            ApiError::TooManyRequests(
+                format!("The daily quota of {} LLM generations is used up", quota.max(0)),
+                (tomorrow - now).num_seconds().max(1) as u64,
+            )
+        };
+        if quota <= 0 {
+            return Err(used_up());
+        }
+
+        // only counts up while under the quota, so nothing is returned once it's reached
+        let usage = diesel::sql_query(
+            "INSERT INTO llm_usage (subject, day, generations) VALUES ($1, $2, 1) \
+             ON CONFLICT (subject, day) DO UPDATE SET generations = llm_usage.generations + 1 \
+             WHERE llm_usage.generations < $3 \
+             RETURNING generations"
+        )
+            .bind::<Varchar, _>(subject)
+            .bind::<Date, _>(now.date())
+            .bind::<Integer, _>(quota)
+            .get_result::<Usage>(conn)
+            .optional()?;
+
+        usage.map(|x| x.generations).ok_or_else(used_up)
+    }
+}
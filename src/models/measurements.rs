@@ -0,0 +1,385 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Millilitres in an ounce, rounded the way bars pour (1 1/2 oz is a 45 ml pour)
+pub const ML_PER_OZ: f64 = 30.0;
+/// Millilitres in a dash of bitters
+pub const ML_PER_DASH: f64 = 0.8;
+/// Millilitres in a barspoon
+pub const ML_PER_BARSPOON: f64 = 5.0;
+/// Past this many dashes or barspoons a batch is measured in ml instead
+const MAX_COUNTED_POURS: f64 = 12.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    #[serde(rename = "oz")]
+    Oz,
+    #[serde(rename = "ml")]
+    Ml,
+    #[serde(rename = "cl")]
+    Cl,
+    #[serde(rename = "dash")]
+    Dash,
+    #[serde(rename = "barspoon")]
+    Barspoon,
+    #[serde(rename = "part")]
+    Part,
+    #[serde(rename = "to taste")]
+    ToTaste,
+}
+
+impl Unit {
+    /// The name the unit is stored and serialized as
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Oz => "oz",
+            Unit::Ml => "ml",
+            Unit::Cl => "cl",
+            Unit::Dash => "dash",
+            Unit::Barspoon => "barspoon",
+            Unit::Part => "part",
+            Unit::ToTaste => "to taste",
+        }
+    }
+
+    /// The unit written out for `quantity`, e.g. "dashes" for 2 dashes
+    pub fn label(&self, quantity: f64) -> &'static str {
+        match self {
+            Unit::Dash if quantity > 1.0 => "dashes",
+            Unit::Barspoon if quantity > 1.0 => "barspoons",
+            Unit::Part if quantity > 1.0 => "parts",
+            unit => unit.as_str(),
+        }
+    }
+
+    /// Units are written in all sorts of ways in recipes
+    fn from_word(word: &str) -> Option<Unit> {
+        match word {
+            "oz" | "ounce" | "ounces" | "fl oz" | "fl. oz" => Some(Unit::Oz),
+            "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => Some(Unit::Ml),
+            "cl" | "centiliter" | "centiliters" | "centilitre" | "centilitres" => Some(Unit::Cl),
+            "dash" | "dashes" => Some(Unit::Dash),
+            "barspoon" | "barspoons" | "bar spoon" | "bar spoons" => Some(Unit::Barspoon),
+            "part" | "parts" => Some(Unit::Part),
+            "to taste" => Some(Unit::ToTaste),
+            _ => None,
+        }
+    }
+
+    fn is_metric(&self) -> bool {
+        matches!(self, Unit::Ml | Unit::Cl)
+    }
+
+    /// Millilitres in one of this unit, `None` for units that aren't a volume
+    pub fn ml(&self) -> Option<f64> {
+        match self {
+            Unit::Oz => Some(ML_PER_OZ),
+            Unit::Ml => Some(1.0),
+            Unit::Cl => Some(10.0),
+            Unit::Dash => Some(ML_PER_DASH),
+            Unit::Barspoon => Some(ML_PER_BARSPOON),
+            Unit::Part | Unit::ToTaste => None,
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Unit::from_word(s).ok_or(())
+    }
+}
+
+/// The unit system amounts are rendered in with `?units=`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    Metric,
+    Imperial,
+}
+
+/// A measurement like "1 1/2 ounces" or "1-2 dashes" taken apart
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Measurement {
+    pub quantity: Option<f64>,
+    /// upper end of a range like "1-2"
+    pub quantity_max: Option<f64>,
+    pub unit: Option<Unit>,
+}
+
+impl Measurement {
+    /// Parses the quantity and unit out of `text`. Whatever isn't understood is left as `None`,
+    /// e.g. "1 egg white" has a quantity but no unit.
+    pub fn parse(text: &str) -> Measurement {
+        let text = text.trim().to_lowercase();
+        let mut measurement = Measurement::default();
+
+        let rest = match parse_number(&text) {
+            Some((quantity, rest)) => {
+                measurement.quantity = Some(quantity);
+                match parse_range_end(rest) {
+                    Some((quantity_max, rest)) => {
+                        measurement.quantity_max = Some(quantity_max);
+                        rest
+                    },
+                    None => rest,
+                }
+            },
+            None => text.as_str(),
+        };
+
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        measurement.unit = words.get(..2)
+            .and_then(|x| Unit::from_word(&x.join(" ")))
+            .or_else(|| words.first().and_then(|x| Unit::from_word(x.trim_end_matches(|c: char| c.is_ascii_punctuation()))));
+
+        if measurement.unit.is_none() && text.contains("to taste") {
+            measurement.unit = Some(Unit::ToTaste);
+        }
+
+        measurement
+    }
+
+    /// Converts ounces and centilitres into `system`, rounded to what can be poured: the
+    /// nearest 1/4 oz or 0.5 ml. Other units don't change.
+    pub fn convert(&self, system: UnitSystem) -> Measurement {
+        let (factor, unit, step) = match (self.unit, system) {
+            (Some(Unit::Oz), UnitSystem::Metric) => (ML_PER_OZ, Unit::Ml, 0.5),
+            (Some(Unit::Cl), UnitSystem::Metric) => (10.0, Unit::Ml, 0.5),
+            (Some(Unit::Ml), UnitSystem::Imperial) => (1.0 / ML_PER_OZ, Unit::Oz, 0.25),
+            (Some(Unit::Cl), UnitSystem::Imperial) => (10.0 / ML_PER_OZ, Unit::Oz, 0.25),
+            _ => return *self,
+        };
+
+        Measurement {
+            quantity: self.quantity.map(|x| round_to(x * factor, step)),
+            quantity_max: self.quantity_max.map(|x| round_to(x * factor, step)),
+            unit: Some(unit),
+        }
+    }
+
+    /// The volume of the (lower) quantity in millilitres, if it is a volume
+    pub fn volume_ml(&self) -> Option<f64> {
+        Some(self.quantity? * self.unit?.ml()?)
+    }
+
+    /// Multiplies the amount by `factor`, rounded to what is practical to measure for a batch:
+    /// 1/4 oz, 5 ml (0.5 ml for small amounts), whole dashes and half barspoons. Large numbers
+    /// of dashes or barspoons are turned into ml.
+    pub fn scale(&self, factor: f64) -> Measurement {
+        let unit = match self.unit {
+            Some(Unit::ToTaste) => return *self,
+            Some(unit @ (Unit::Dash | Unit::Barspoon)) if self.quantity.unwrap_or(0.0) * factor > MAX_COUNTED_POURS => {
+                let ml = unit.ml().unwrap_or(1.0);
+                return Measurement {
+                    quantity: self.quantity.map(|x| round_ml(x * factor * ml)),
+                    quantity_max: self.quantity_max.map(|x| round_ml(x * factor * ml)),
+                    unit: Some(Unit::Ml),
+                };
+            },
+            unit => unit,
+        };
+
+        let round = |x: f64| match unit {
+            Some(Unit::Oz) | Some(Unit::Part) => round_to(x, 0.25),
+            Some(Unit::Ml) => round_ml(x),
+            Some(Unit::Cl) | Some(Unit::Barspoon) => round_to(x, 0.5),
+            Some(Unit::Dash) => round_to(x, 1.0),
+            // egg whites, lime wedges, ...
+            _ => round_to(x, 0.5),
+        };
+
+        Measurement {
+            quantity: self.quantity.map(|x| round(x * factor)),
+            quantity_max: self.quantity_max.map(|x| round(x * factor)),
+            unit,
+        }
+    }
+
+    /// Writes the measurement back out, e.g. "1 1/2 oz" or "45 ml". `None` when there is
+    /// nothing to write.
+    pub fn render(&self) -> Option<String> {
+        let metric = self.unit.is_some_and(|x| x.is_metric());
+        let format = |x: f64| if metric { format_decimal(x) } else { format_fraction(x) };
+
+        let amount = match (self.quantity, self.quantity_max) {
+            (Some(q), Some(q_max)) => Some(format!("{}-{}", format(q), format(q_max))),
+            (Some(q), None) => Some(format(q)),
+            _ => None,
+        };
+        let unit = self.unit.map(|x| x.label(self.quantity_max.or(self.quantity).unwrap_or(1.0)));
+
+        match (amount, unit) {
+            (Some(amount), Some(unit)) => Some(format!("{} {}", amount, unit)),
+            (Some(amount), None) => Some(amount),
+            (None, Some(unit)) => Some(unit.to_string()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Rounds `x` to the nearest multiple of `step`, without rounding a non zero amount away
+pub fn round_to(x: f64, step: f64) -> f64 {
+    let steps = (x / step).round();
+    // steps below 1 are 1/n, dividing by n keeps 0.1 steps from coming out as 22.900000000000002
+    let rounded = if step < 1.0 { steps / (1.0 / step).round() } else { steps * step };
+    if rounded == 0.0 && x > 0.0 {
+        step
+    } else {
+        rounded
+    }
+}
+
+fn round_ml(x: f64) -> f64 {
+    if x >= 50.0 {
+        round_to(x, 5.0)
+    } else {
+        round_to(x, 0.5)
+    }
+}
+
+/// Parses a volume like "1l", "750 ml", "70cl" or "32 oz" into millilitres. A bare number is
+/// taken to be ml.
+pub fn parse_volume(text: &str) -> Option<f64> {
+    let text = text.trim().to_lowercase();
+    let (amount, rest) = parse_number(&text)?;
+    let factor = match rest.trim() {
+        "" | "ml" | "milliliters" | "millilitres" => 1.0,
+        "cl" | "centiliters" | "centilitres" => 10.0,
+        "l" | "liter" | "liters" | "litre" | "litres" => 1000.0,
+        "oz" | "ounces" | "fl oz" => ML_PER_OZ,
+        _ => return None,
+    };
+    Some(amount * factor)
+}
+
+/// Splits a leading decimal number like "1" or "1.5" off `s`
+fn split_decimal(s: &str) -> Option<(f64, &str)> {
+    let end = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    s[..end].parse::<f64>().ok().map(|x| (x, &s[end..]))
+}
+
+/// Splits a leading unicode vulgar fraction like "½" off `s`
+fn split_unicode_fraction(s: &str) -> Option<(f64, &str)> {
+    let c = s.chars().next()?;
+    let value = match c {
+        '¼' => 0.25,
+        '½' => 0.5,
+        '¾' => 0.75,
+        '⅓' => 1.0 / 3.0,
+        '⅔' => 2.0 / 3.0,
+        '⅛' => 0.125,
+        _ => return None,
+    };
+    Some((value, &s[c.len_utf8()..]))
+}
+
+/// Splits a fraction like "1/2" off `s`
+fn split_fraction(s: &str) -> Option<(f64, &str)> {
+    let (numerator, rest) = split_decimal(s)?;
+    let (denominator, rest) = split_decimal(rest.strip_prefix('/')?)?;
+    if denominator == 0.0 {
+        return None;
+    }
+    Some((numerator / denominator, rest))
+}
+
+/// Splits a leading amount like "2", "1/2", "1 1/2", "1½" or "½" off `s`
+fn parse_number(s: &str) -> Option<(f64, &str)> {
+    let s = s.trim_start();
+    if let Some(fraction) = split_fraction(s).or_else(|| split_unicode_fraction(s)) {
+        return Some(fraction);
+    }
+
+    let (whole, rest) = split_decimal(s)?;
+    let trimmed = rest.trim_start();
+    match split_fraction(trimmed).or_else(|| split_unicode_fraction(trimmed)) {
+        Some((fraction, rest)) if fraction < 1.0 => Some((whole + fraction, rest)),
+        _ => Some((whole, rest)),
+    }
+}
+
+/// Splits the upper end of a range like "-2" or "to 2" off `s`
+fn parse_range_end(s: &str) -> Option<(f64, &str)> {
+    let s = s.trim_start();
+    let rest = s.strip_prefix('-')
+        .or_else(|| s.strip_prefix('–'))
+        .or_else(|| s.strip_prefix("to "))?;
+    parse_number(rest)
+}
+
+fn format_decimal(x: f64) -> String {
+    let formatted = format!("{:.2}", x);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Writes quarters, thirds and halves as fractions, e.g. "1 1/2"
+fn format_fraction(x: f64) -> String {
+    let whole = x.trunc();
+    let fraction = x - whole;
+    let named = [(0.25, "1/4"), (1.0 / 3.0, "1/3"), (0.5, "1/2"), (2.0 / 3.0, "2/3"), (0.75, "3/4")]
+        .into_iter()
+        .find(|(value, _)| (fraction - value).abs() < 0.01)
+        .map(|(_, name)| name);
+
+    match named {
+        Some(name) if whole == 0.0 => name.to_string(),
+        Some(name) => format!("{} {}", whole, name),
+        None => format_decimal(x),
+    }
+}
+
+
+#[test]
+pub fn test_parse_measurement() {
+    let parse = |x: &str| {
+        let m = Measurement::parse(x);
+        (m.quantity, m.quantity_max, m.unit)
+    };
+
+    assert_eq!(parse("2 ounces"), (Some(2.0), None, Some(Unit::Oz)));
+    assert_eq!(parse("1/2 ounce"), (Some(0.5), None, Some(Unit::Oz)));
+    assert_eq!(parse("1 1/2 oz"), (Some(1.5), None, Some(Unit::Oz)));
+    assert_eq!(parse("1½ fl oz"), (Some(1.5), None, Some(Unit::Oz)));
+    assert_eq!(parse("30ml"), (Some(30.0), None, Some(Unit::Ml)));
+    assert_eq!(parse("2.5 cl"), (Some(2.5), None, Some(Unit::Cl)));
+    assert_eq!(parse("1-2 dashes"), (Some(1.0), Some(2.0), Some(Unit::Dash)));
+    assert_eq!(parse("1 to 2 bar spoons"), (Some(1.0), Some(2.0), Some(Unit::Barspoon)));
+    assert_eq!(parse("2 parts"), (Some(2.0), None, Some(Unit::Part)));
+    assert_eq!(parse("Salt, to taste"), (None, None, Some(Unit::ToTaste)));
+    assert_eq!(parse("1 egg white"), (Some(1.0), None, None));
+    assert_eq!(parse("Top up"), (None, None, None));
+}
+
+#[test]
+pub fn test_convert_measurement() {
+    let oz = Measurement::parse("1 1/2 oz");
+    assert_eq!(oz.convert(UnitSystem::Metric).render(), Some("45 ml".to_string()));
+    assert_eq!(oz.convert(UnitSystem::Imperial).render(), Some("1 1/2 oz".to_string()));
+
+    let ml = Measurement::parse("50 ml");
+    assert_eq!(ml.convert(UnitSystem::Imperial).render(), Some("1 3/4 oz".to_string()));
+
+    let cl = Measurement::parse("2.5 cl");
+    assert_eq!(cl.convert(UnitSystem::Metric).render(), Some("25 ml".to_string()));
+
+    let dashes = Measurement::parse("1-2 dashes");
+    assert_eq!(dashes.convert(UnitSystem::Metric).render(), Some("1-2 dashes".to_string()));
+}
+
+#[test]
+pub fn test_scale_measurement() {
+    assert_eq!(Measurement::parse("3/4 oz").scale(10.0).render(), Some("7 1/2 oz".to_string()));
+    assert_eq!(Measurement::parse("22.5 ml").scale(3.0).render(), Some("70 ml".to_string()));
+    assert_eq!(Measurement::parse("22.5 ml").scale(20.0).render(), Some("450 ml".to_string()));
+    assert_eq!(Measurement::parse("2 dashes").scale(3.0).render(), Some("6 dashes".to_string()));
+    assert_eq!(Measurement::parse("2 dashes").scale(20.0).render(), Some("32 ml".to_string()));
+    assert_eq!(Measurement::parse("to taste").scale(20.0).render(), Some("to taste".to_string()));
+
+    assert_eq!(parse_volume("1l"), Some(1000.0));
+    assert_eq!(parse_volume("70 cl"), Some(700.0));
+    assert_eq!(parse_volume("750"), Some(750.0));
+    assert_eq!(parse_volume("a bucket"), None);
+}
@@ -1,4 +1,17 @@
 pub mod todo;
 pub mod cocktails;
 pub mod ingredients;
+pub mod canonical_ingredients;
 pub mod instructions;
+pub mod generation_jobs;
+pub mod similarity;
+pub mod measurements;
+pub mod preparation;
+pub mod batches;
+pub mod strength;
+pub mod cursors;
+pub mod api_keys;
+pub mod users;
+pub mod llm_usage;
+pub mod stats;
+pub mod health;
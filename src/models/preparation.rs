@@ -0,0 +1,59 @@
+use serde::Serialize;
+
+use super::instructions::InstructionData;
+
+/// Share of a drink's volume that is melted ice after shaking, stirring or building over ice
+const SHAKEN_DILUTION: f64 = 0.25;
+const STIRRED_DILUTION: f64 = 0.20;
+const BUILT_DILUTION: f64 = 0.10;
+
+/// How a cocktail is mixed, which decides how much it gets diluted
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Shaken,
+    Stirred,
+    Built,
+}
+
+impl Method {
+    /// Works out the method from the instructions. Anything that isn't shaken or stirred is
+    /// taken to be built in the glass.
+    pub fn from_instructions(instructions: &[InstructionData]) -> Method {
+        let steps: Vec<&str> = instructions.iter().map(|x| x.instruction.as_str()).collect();
+        Method::from_steps(&steps)
+    }
+
+    pub fn from_steps(steps: &[&str]) -> Method {
+        let text = steps.join(" ").to_lowercase();
+
+        if text.contains("shake") {
+            Method::Shaken
+        } else if text.contains("stir") {
+            Method::Stirred
+        } else {
+            Method::Built
+        }
+    }
+
+    pub fn dilution(&self) -> f64 {
+        match self {
+            Method::Shaken => SHAKEN_DILUTION,
+            Method::Stirred => STIRRED_DILUTION,
+            Method::Built => BUILT_DILUTION,
+        }
+    }
+}
+
+
+#[test]
+pub fn test_method_from_instructions() {
+    let instructions = |steps: &[&str]| steps.iter()
+        .enumerate()
+        .map(|(i, x)| InstructionData::from((i, x.to_string())))
+        .collect::<Vec<InstructionData>>();
+
+    assert_eq!(Method::from_instructions(&instructions(&["Shake with ice.", "Strain."])), Method::Shaken);
+    assert_eq!(Method::from_instructions(&instructions(&["Stir with ice until cold."])), Method::Stirred);
+    assert_eq!(Method::from_instructions(&instructions(&["Pour over ice.", "Top with soda."])), Method::Built);
+}
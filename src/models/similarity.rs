@@ -0,0 +1,68 @@
+/// Upper cases `value` and collapses runs of whitespace, like the `unique_name_normalized` index
+pub fn normalize_name(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join(" ").to_uppercase()
+}
+
+/// 1.0 for names that normalize to the same string, going down to 0.0 as more edits
+/// are needed to turn one into the other
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = normalize_name(a).chars().collect();
+    let b: Vec<char> = normalize_name(b).chars().collect();
+    let longest = a.len().max(b.len());
+
+    if longest == 0 {
+        return 1.0;
+    }
+
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+/// Share of ingredients the two lists have in common, where ingredients with similar
+/// names ("Whisky", "Whiskey") count as the same
+pub fn ingredient_overlap(a: &[String], b: &[String], min_similarity: f64) -> f64 {
+    let longest = a.len().max(b.len());
+
+    if longest == 0 {
+        return 0.0;
+    }
+
+    let shared = a.iter()
+        .filter(|a_name| b.iter().any(|b_name| name_similarity(a_name, b_name) >= min_similarity))
+        .count();
+
+    shared as f64 / longest as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = if a_char == b_char { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+
+#[test]
+pub fn test_name_similarity() {
+    assert_eq!(normalize_name("  whiskey   Sour "), "WHISKEY SOUR");
+    assert_eq!(name_similarity("Whiskey Sour", "WHISKEY  SOUR"), 1.0);
+    assert!(name_similarity("Whisky Sour", "Whiskey Sour") > 0.9);
+    assert!(name_similarity("Gimlet", "Negroni") < 0.5);
+}
+
+#[test]
+pub fn test_ingredient_overlap() {
+    let a = vec!["Whisky".to_owned(), "Lemon juice".to_owned(), "Simple syrup".to_owned()];
+    let b = vec!["Whiskey".to_owned(), "lemon juice".to_owned(), "Simple syrup".to_owned(), "Egg white".to_owned()];
+
+    assert_eq!(ingredient_overlap(&a, &b, 0.8), 0.75);
+    assert_eq!(ingredient_overlap(&a, &[], 0.8), 0.0);
+}
@@ -0,0 +1,30 @@
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel::QueryableByName;
+
+use crate::error::ApiError;
+use crate::repository::database::Database;
+
+/// How much is stored, for the gauges on /metrics
+#[derive(QueryableByName, Debug, Clone, Copy, PartialEq)]
+pub struct Counts {
+    #[diesel(sql_type = BigInt)]
+    pub cocktails: i64,
+    /// distinct (canonical) ingredients rather than ingredient lines of cocktails
+    #[diesel(sql_type = BigInt)]
+    pub ingredients: i64,
+    #[diesel(sql_type = BigInt)]
+    pub todos: i64,
+}
+
+impl Counts {
+    pub fn get_counts(db: &Database) -> Result<Counts, ApiError> {
+        let counts = diesel::sql_query(
+            "SELECT (SELECT COUNT(*) FROM cocktails) AS cocktails, \
+             (SELECT COUNT(*) FROM canonical_ingredients) AS ingredients, \
+             (SELECT COUNT(*) FROM todos) AS todos"
+        )
+            .get_result::<Counts>(&mut db.pool.get()?)?;
+        Ok(counts)
+    }
+}
@@ -0,0 +1,145 @@
+use serde::Serialize;
+
+use super::measurements::{round_to, Measurement};
+use super::preparation::Method;
+
+/// Grams of alcohol in a standard drink (US)
+const GRAMS_PER_STANDARD_DRINK: f64 = 14.0;
+/// Grams per ml of ethanol
+const ETHANOL_DENSITY: f64 = 0.789;
+
+/// ABV (percent) of common ingredients, used for ingredients added to the catalogue.
+/// Keys are normalized ingredient names.
+const DEFAULT_ABVS: &[(&str, f64)] = &[
+    ("gin", 40.0),
+    ("london dry gin", 40.0),
+    ("vodka", 40.0),
+    ("whiskey", 40.0),
+    ("whisky", 40.0),
+    ("scotch", 40.0),
+    ("scotch whisky", 40.0),
+    ("bourbon", 45.0),
+    ("bourbon whiskey", 45.0),
+    ("rye", 45.0),
+    ("rye whiskey", 45.0),
+    ("rum", 40.0),
+    ("white rum", 40.0),
+    ("light rum", 40.0),
+    ("dark rum", 40.0),
+    ("tequila", 40.0),
+    ("blanco tequila", 40.0),
+    ("mezcal", 40.0),
+    ("brandy", 40.0),
+    ("cognac", 40.0),
+    ("pisco", 40.0),
+    ("absinthe", 60.0),
+    ("triple sec", 30.0),
+    ("cointreau", 40.0),
+    ("orange liqueur", 30.0),
+    ("campari", 24.0),
+    ("aperol", 11.0),
+    ("sweet vermouth", 16.0),
+    ("dry vermouth", 18.0),
+    ("vermouth", 16.0),
+    ("angostura bitters", 44.7),
+    ("bitters", 44.7),
+    ("orange bitters", 28.0),
+    ("amaretto", 24.0),
+    ("coffee liqueur", 20.0),
+    ("kahlua", 20.0),
+    ("maraschino liqueur", 32.0),
+    ("green chartreuse", 55.0),
+    ("yellow chartreuse", 40.0),
+    ("benedictine", 40.0),
+    ("elderflower liqueur", 20.0),
+    ("prosecco", 11.0),
+    ("champagne", 12.0),
+    ("sparkling wine", 12.0),
+    ("lemon juice", 0.0),
+    ("lime juice", 0.0),
+    ("orange juice", 0.0),
+    ("grapefruit juice", 0.0),
+    ("pineapple juice", 0.0),
+    ("cranberry juice", 0.0),
+    ("simple syrup", 0.0),
+    ("sugar syrup", 0.0),
+    ("honey syrup", 0.0),
+    ("grenadine", 0.0),
+    ("club soda", 0.0),
+    ("soda water", 0.0),
+    ("tonic water", 0.0),
+    ("ginger beer", 0.0),
+    ("cola", 0.0),
+    ("water", 0.0),
+];
+
+/// The ABV of a well known ingredient, `normalized_name` as stored in ingredient_aliases
+pub fn default_abv(normalized_name: &str) -> Option<f64> {
+    DEFAULT_ABVS.iter()
+        .find(|(known, _)| *known == normalized_name)
+        .map(|(_, abv)| *abv)
+}
+
+/// How strong a cocktail is once mixed
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Strength {
+    /// percent alcohol by volume
+    pub abv: f64,
+    pub standard_drinks: f64,
+}
+
+impl Strength {
+    /// Estimates the strength from the measurement and ABV of each ingredient, counting the water
+    /// `method` adds. `None` when there is no volume to go on or a measured ingredient has an
+    /// unknown ABV, an unknown spirit would otherwise pass for juice.
+    pub fn estimate(ingredients: &[(Measurement, Option<f64>)], method: Method) -> Option<Strength> {
+        let measured: Vec<(f64, Option<f64>)> = ingredients.iter()
+            .filter_map(|(m, abv)| Some((m.volume_ml()?, *abv)))
+            .collect();
+        let volume_ml: f64 = measured.iter().map(|(ml, _)| ml).sum();
+        if volume_ml == 0.0 {
+            return None;
+        }
+
+        let alcohol_ml: f64 = measured.iter()
+            .map(|(ml, abv)| Some(ml * (*abv)? / 100.0))
+            .sum::<Option<f64>>()?;
+        let diluted_ml = volume_ml * (1.0 + method.dilution());
+
+        Some(Strength {
+            abv: round_to(alcohol_ml / diluted_ml * 100.0, 0.1),
+            standard_drinks: round_to(alcohol_ml * ETHANOL_DENSITY / GRAMS_PER_STANDARD_DRINK, 0.1),
+        })
+    }
+}
+
+
+#[test]
+pub fn test_estimate_strength() {
+    let ingredients = vec![
+        (Measurement::parse("2 oz"), Some(40.0)),
+        (Measurement::parse("1 oz"), Some(0.0)),
+        (Measurement::parse("3/4 oz"), Some(0.0)),
+        // no volume, so its ABV doesn't matter
+        (Measurement::parse("1 egg white"), None),
+    ];
+
+    // 24 ml of alcohol in 112.5 ml, plus 25% water from shaking
+    let shaken = Strength::estimate(&ingredients, Method::Shaken).unwrap();
+    assert!((shaken.abv - 17.1).abs() < 0.01);
+    assert!((shaken.standard_drinks - 1.4).abs() < 0.01);
+
+    let built = Strength::estimate(&ingredients, Method::Built).unwrap();
+    assert!(built.abv > shaken.abv);
+
+    assert_eq!(Strength::estimate(&[(Measurement::parse("garnish"), Some(40.0))], Method::Built), None);
+
+    // an unrecognised spirit makes the estimate unknown rather than low
+    let unknown_spirit = vec![
+        (Measurement::parse("2 oz"), None),
+        (Measurement::parse("4 oz"), Some(0.0)),
+    ];
+    assert_eq!(Strength::estimate(&unknown_spirit, Method::Built), None);
+    assert_eq!(default_abv("gin"), Some(40.0));
+    assert_eq!(default_abv("lemon juice"), Some(0.0));
+}
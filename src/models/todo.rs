@@ -1,11 +1,11 @@
-use std::fmt::Error;
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
-use diesel::{Queryable, Insertable, AsChangeset, RunQueryDsl, QueryDsl};
+use diesel::{Queryable, Insertable, AsChangeset, RunQueryDsl, QueryDsl, OptionalExtension};
 
 use crate::repository::schema::todos::dsl::*;
 use crate::repository::schema::categories::dsl::*;
 
+use crate::error::ApiError;
 use crate::repository::database::Database;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Queryable, Insertable, AsChangeset)]
@@ -18,6 +18,9 @@ pub struct Todo {
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
     pub category_id: Option<i32>,
+    /// the user that added the todo, ignored when writing
+    #[serde(default)]
+    pub created_by: Option<uuid::Uuid>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Queryable, Insertable, AsChangeset)]
@@ -44,22 +47,22 @@ pub struct TodoItemData {
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
     pub category: Option<CategoryData>,
+    pub created_by: Option<uuid::Uuid>,
 }
 
 impl Todo {
-    pub fn get_categories(db: &Database) -> Vec<Category> {
-        categories
-            .load::<Category>(&mut db.pool.get().unwrap())
-            .expect("Error loading all categories")
+    pub fn get_categories(db: &Database) -> Result<Vec<Category>, ApiError> {
+        let results = categories
+            .load::<Category>(&mut db.pool.get()?)?;
+        Ok(results)
     }
 
-    pub fn get_todo_with_category(db: &Database) -> Vec<TodoItemData> {
+    pub fn get_todo_with_category(db: &Database) -> Result<Vec<TodoItemData>, ApiError> {
         let mut empty_todo_item_data_list: Vec<TodoItemData> = Vec::new();
 
         todos
             .inner_join(categories)
-            .load::<(Todo, Category)>(&mut db.pool.get().unwrap())
-            .expect("Error loading all todos")
+            .load::<(Todo, Category)>(&mut db.pool.get()?)?
             .into_iter()
             .for_each(|(todo, category)| {
                 let todo_item_data = TodoItemData { 
@@ -72,54 +75,62 @@ impl Todo {
                         id: category.id,
                         name: category.name,
                         description: category.description
-                    })
+                    }),
+                    created_by: todo.created_by,
                 };
                 empty_todo_item_data_list.push(todo_item_data)
             });
-        empty_todo_item_data_list
+        Ok(empty_todo_item_data_list)
     }
 
-    pub fn get_todos(db: &Database) -> Vec<Todo> {
-        todos
-            .load::<Todo>(&mut db.pool.get().unwrap())
-            .expect("Error loading all todos")
+    pub fn get_todos(db: &Database) -> Result<Vec<Todo>, ApiError> {
+        let results = todos
+            .load::<Todo>(&mut db.pool.get()?)?;
+        Ok(results)
     }
 
-    pub fn create_todo(db: &Database, todo: Todo) -> Result<Todo, Error> {
+    /// `user_id` is the user adding the todo, if any
+    pub fn create_todo(db: &Database, todo: Todo, user_id: Option<uuid::Uuid>) -> Result<Todo, ApiError> {
         let todo = Todo {
             id: uuid::Uuid::new_v4().to_string(),
+            created_by: user_id,
             created_at: Some(Utc::now().naive_utc()),
             updated_at: Some(Utc::now().naive_utc()),
             ..todo
         };
         diesel::insert_into(todos)
             .values(&todo)
-            .execute(&mut db.pool.get().unwrap())
-            .expect("Error creating new todo");
+            .execute(&mut db.pool.get()?)?;
         Ok(todo)
     }
 
-    pub fn get_todo_by_id(db: &Database, todo_id: &str) -> Option<Todo> {
+    pub fn get_todo_by_id(db: &Database, todo_id: &str) -> Result<Todo, ApiError> {
         let todo = todos
             .find(todo_id)
-            .get_result::<Todo>(&mut db.pool.get().unwrap())
-            .expect("Error loading todo by id");
-        Some(todo)
+            .get_result::<Todo>(&mut db.pool.get()?)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound("Todo not found".to_string()))?;
+        Ok(todo)
     }
 
-    pub fn delete_todo_by_id(db: &Database, todo_id: &str) -> Option<usize> {
+    pub fn delete_todo_by_id(db: &Database, todo_id: &str) -> Result<usize, ApiError> {
         let count = diesel::delete(todos.find(todo_id))
-            .execute(&mut db.pool.get().unwrap())
-            .expect("Error deleting todo by id");
-        Some(count)
+            .execute(&mut db.pool.get()?)?;
+        if count == 0 {
+            return Err(ApiError::NotFound("Todo not found".to_string()));
+        }
+        Ok(count)
     }
 
-    pub fn update_todo_by_id(db: &Database, todo_id: &str, mut todo: Todo) -> Option<Todo> {
+    pub fn update_todo_by_id(db: &Database, todo_id: &str, mut todo: Todo) -> Result<Todo, ApiError> {
         todo.updated_at = Some(Utc::now().naive_utc());
+        // None leaves it as it is
+        todo.created_by = None;
         let todo = diesel::update(todos.find(todo_id))
             .set(&todo)
-            .get_result::<Todo>(&mut db.pool.get().unwrap())
-            .expect("Error updating todo by id");
-        Some(todo)
+            .get_result::<Todo>(&mut db.pool.get()?)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound("Todo not found".to_string()))?;
+        Ok(todo)
     }
 }
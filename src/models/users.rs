@@ -0,0 +1,186 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::Utc;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Text};
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, Selectable, Identifiable};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config;
+use crate::error::ApiError;
+use crate::repository::schema::users::dsl::*;
+use crate::repository::database::Database;
+
+/// How long a token from login is good for
+const TOKEN_TTL_HOURS: i64 = 24;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_USERNAME_LENGTH: usize = 50;
+
+/// A bartender using the app
+#[derive(Serialize, Debug, Clone, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = crate::repository::schema::users)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// What login hands back, sent as `Authorization: Bearer <token>` afterwards
+#[derive(Serialize, Debug, Clone)]
+pub struct LoginToken {
+    pub token: String,
+    pub token_type: String,
+    /// seconds until the token expires
+    pub expires_in: i64,
+    pub user: User,
+}
+
+/// Claims of the tokens issued on login
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Claims {
+    /// user id
+    pub sub: Uuid,
+    pub username: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// The secret tokens are signed with (HS256)
+fn jwt_secret() -> Result<&'static str, ApiError> {
+    match &config::get().auth.jwt_secret {
+        Some(secret) if !secret.is_empty() => Ok(secret),
+        _ => Err(ApiError::Internal("auth.jwt_secret (JWT_SECRET) is not set".to_string())),
+    }
+}
+
+impl User {
+    pub fn register(db: &Database, credentials: Credentials) -> Result<User, ApiError> {
+        let name = credentials.username.trim();
+        if name.is_empty() || name.chars().count() > MAX_USERNAME_LENGTH {
+            return Err(ApiError::BadRequest(
+                format!("username must be between 1 and {} characters", MAX_USERNAME_LENGTH)
+            ));
+        }
+        if credentials.password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(ApiError::BadRequest(
+                format!("password must be at least {} characters", MIN_PASSWORD_LENGTH)
+            ));
+        }
+
+        let salt = SaltString::generate(&mut OsRng);
+        let hashed = Argon2::default()
+            .hash_password(credentials.password.as_bytes(), &salt)
+            .map_err(|err| ApiError::Internal(format!("Could not hash password: {}", err)))?
+            .to_string();
+
+        let user = User {
+            id: Uuid::new_v4(),
+            username: name.to_string(),
+            password_hash: hashed,
+            created_at: Some(Utc::now().naive_utc()),
+            updated_at: Some(Utc::now().naive_utc()),
+        };
+
+        let user = diesel::insert_into(users)
+            .values(&user)
+            .get_result::<User>(&mut db.pool.get()?)?;
+        Ok(user)
+    }
+
+    pub fn get_user_by_id(db: &Database, user_id: Uuid) -> Result<User, ApiError> {
+        let user = users.find(user_id)
+            .get_result::<User>(&mut db.pool.get()?)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+        Ok(user)
+    }
+
+    /// Checks `credentials` and issues a token for the user
+    pub fn login(db: &Database, credentials: Credentials) -> Result<LoginToken, ApiError> {
+        let invalid = || ApiError::Unauthorized("Invalid username or password".to_string());
+
+        // the left hand side has to match the unique_username index expression
+        let user = users
+            .filter(
+                sql::<Bool>("LOWER(username) = LOWER(")
+                    .bind::<Text, _>(credentials.username.trim())
+                    .sql(")")
+            )
+            .first::<User>(&mut db.pool.get()?)
+            .optional()?
+            .ok_or_else(invalid)?;
+
+        let parsed = PasswordHash::new(&user.password_hash)
+            .map_err(|err| ApiError::Internal(format!("Stored password hash is invalid: {}", err)))?;
+        Argon2::default()
+            .verify_password(credentials.password.as_bytes(), &parsed)
+            .map_err(|_| invalid())?;
+
+        let token = User::issue_token(&user)?;
+        Ok(LoginToken {
+            token,
+            token_type: "Bearer".to_string(),
+            expires_in: TOKEN_TTL_HOURS * 60 * 60,
+            user,
+        })
+    }
+
+    pub fn issue_token(user: &User) -> Result<String, ApiError> {
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: user.id,
+            username: user.username.clone(),
+            iat: now,
+            exp: now + TOKEN_TTL_HOURS * 60 * 60,
+        };
+        encode_claims(&claims, jwt_secret()?)
+    }
+
+    /// The claims of `token` if it was issued by us and hasn't expired. Without a secret none
+    /// was, so it's a 401 rather than the 500 of issuing one.
+    pub fn verify_token(token: &str) -> Result<Claims, ApiError> {
+        let secret = jwt_secret()
+            .map_err(|_| ApiError::Unauthorized("Invalid API key or token".to_string()))?;
+        decode_claims(token, secret)
+    }
+}
+
+fn encode_claims(claims: &Claims, secret: &str) -> Result<String, ApiError> {
+    encode(&Header::new(Algorithm::HS256), claims, &EncodingKey::from_secret(secret.as_bytes()))
+        .map_err(|err| ApiError::Internal(format!("Could not sign token: {}", err)))
+}
+
+fn decode_claims(token: &str, secret: &str) -> Result<Claims, ApiError> {
+    decode::<Claims>(token, &DecodingKey::from_secret(secret.as_bytes()), &Validation::new(Algorithm::HS256))
+        .map(|x| x.claims)
+        .map_err(|_| ApiError::Unauthorized("Invalid or expired token".to_string()))
+}
+
+
+#[test]
+pub fn test_token_claims() {
+    let now = Utc::now().timestamp();
+    let claims = Claims { sub: Uuid::new_v4(), username: "sam".to_owned(), iat: now, exp: now + 60 };
+
+    let token = encode_claims(&claims, "secret").unwrap();
+    assert_eq!(decode_claims(&token, "secret").unwrap(), claims);
+    assert!(decode_claims(&token, "other secret").is_err());
+    // tests run without a JWT_SECRET
+    assert!(matches!(User::verify_token(&token), Err(ApiError::Unauthorized(_))));
+
+    let expired = Claims { iat: now - 7200, exp: now - 3600, ..claims };
+    let token = encode_claims(&expired, "secret").unwrap();
+    assert!(decode_claims(&token, "secret").is_err());
+}
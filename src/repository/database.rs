@@ -1,6 +1,9 @@
+use std::time::Duration;
+
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
-use dotenv::dotenv;
+use diesel::r2d2::{self, ConnectionManager, PoolError};
+
+use crate::config::DatabaseConfig;
 
 pub type DBPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
@@ -10,13 +13,14 @@ pub struct Database {
 }
 
 impl Database {
-    pub fn new() -> Self {
-        dotenv().ok();
-        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let manager = ConnectionManager::<PgConnection>::new(database_url);
+    /// Opens the pool, failing when no connection can be made within the timeout
+    pub fn new(config: &DatabaseConfig) -> Result<Self, PoolError> {
+        let manager = ConnectionManager::<PgConnection>::new(&config.url);
         let pool: DBPool = r2d2::Pool::builder()
-            .build(manager)
-            .expect("Failed to create pool.");
-        Database { pool }
+            .max_size(config.pool_size)
+            .min_idle(config.min_idle)
+            .connection_timeout(Duration::from_secs(config.connection_timeout_secs))
+            .build(manager)?;
+        Ok(Database { pool })
     }
 }
@@ -1,5 +1,27 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Uuid,
+        name -> Varchar,
+        key_hash -> Varchar,
+        scopes -> Array<Text>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    canonical_ingredients (id) {
+        id -> Uuid,
+        name -> Varchar,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        abv -> Nullable<Float8>,
+    }
+}
+
 diesel::table! {
     categories (id) {
         id -> Int4,
@@ -14,6 +36,31 @@ diesel::table! {
         name -> Varchar,
         created_at -> Nullable<Timestamp>,
         updated_at -> Nullable<Timestamp>,
+        abv -> Nullable<Float8>,
+        standard_drinks -> Nullable<Float8>,
+        created_by -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    generation_jobs (id) {
+        id -> Uuid,
+        ingredients -> Array<Text>,
+        status -> Varchar,
+        error -> Nullable<Text>,
+        cocktail_ids -> Array<Uuid>,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        claimed_by -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    ingredient_aliases (alias) {
+        alias -> Varchar,
+        canonical_ingredient_id -> Uuid,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
     }
 }
 
@@ -25,6 +72,10 @@ diesel::table! {
         cocktail_id -> Uuid,
         created_at -> Nullable<Timestamp>,
         updated_at -> Nullable<Timestamp>,
+        canonical_ingredient_id -> Nullable<Uuid>,
+        quantity -> Nullable<Float8>,
+        quantity_max -> Nullable<Float8>,
+        unit -> Nullable<Varchar>,
     }
 }
 
@@ -39,6 +90,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    llm_usage (subject, day) {
+        subject -> Varchar,
+        day -> Date,
+        generations -> Int4,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     todos (id) {
         id -> Varchar,
@@ -47,17 +108,38 @@ diesel::table! {
         created_at -> Nullable<Timestamp>,
         updated_at -> Nullable<Timestamp>,
         category_id -> Nullable<Int4>,
+        created_by -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Uuid,
+        username -> Varchar,
+        password_hash -> Text,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
     }
 }
 
+diesel::joinable!(ingredient_aliases -> canonical_ingredients (canonical_ingredient_id));
+diesel::joinable!(ingredients -> canonical_ingredients (canonical_ingredient_id));
 diesel::joinable!(ingredients -> cocktails (cocktail_id));
 diesel::joinable!(instructions -> cocktails (cocktail_id));
+diesel::joinable!(cocktails -> users (created_by));
 diesel::joinable!(todos -> categories (category_id));
+diesel::joinable!(todos -> users (created_by));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    canonical_ingredients,
     categories,
     cocktails,
+    generation_jobs,
+    ingredient_aliases,
     ingredients,
     instructions,
+    llm_usage,
     todos,
+    users,
 );
@@ -0,0 +1,99 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::{global, Context, KeyValue};
+
+use crate::llm::pricing::estimate_cost;
+use crate::llm::TokenUsage;
+
+/// Name of the LLM latency histogram, it gets buckets of its own
+pub const LLM_DURATION: &str = "llm.duration";
+
+static LLM_METRICS: OnceLock<LlmMetrics> = OnceLock::new();
+
+/// Counters and histograms of the calls to the LLM providers, labeled by provider and model
+pub struct LlmMetrics {
+    requests: Counter<u64>,
+    duration: Histogram<f64>,
+    prompt_tokens: Counter<u64>,
+    completion_tokens: Counter<u64>,
+    cost: Counter<f64>,
+    parse_failures: Counter<u64>,
+    cocktails: Counter<u64>,
+}
+
+/// The LLM metrics, created on first use. That has to be after `OpenTelemetryStack::new` set
+/// the meter provider or they are dropped.
+pub fn llm_metrics() -> &'static LlmMetrics {
+    LLM_METRICS.get_or_init(LlmMetrics::new)
+}
+
+fn labels(provider: &str, model: &str) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new("provider", provider.to_string()),
+        KeyValue::new("model", model.to_string()),
+    ]
+}
+
+impl LlmMetrics {
+    fn new() -> LlmMetrics {
+        let meter = global::meter("bartender");
+        LlmMetrics {
+            requests: meter.u64_counter("llm.requests")
+                .with_description("Completions requested from the LLM, by outcome (ok or error)")
+                .init(),
+            duration: meter.f64_histogram(LLM_DURATION)
+                .with_description("How long completions took, in seconds")
+                .init(),
+            prompt_tokens: meter.u64_counter("llm.prompt_tokens")
+                .with_description("Tokens sent to the LLM")
+                .init(),
+            completion_tokens: meter.u64_counter("llm.completion_tokens")
+                .with_description("Tokens the LLM answered with")
+                .init(),
+            cost: meter.f64_counter("llm.cost_usd")
+                .with_description("Estimated cost of the completions in USD")
+                .init(),
+            parse_failures: meter.u64_counter("llm.parse_failures")
+                .with_description("Completions no cocktails could be parsed from")
+                .init(),
+            cocktails: meter.u64_counter("llm.cocktails")
+                .with_description("Cocktails the LLM came up with, by result (created or duplicate)")
+                .init(),
+        }
+    }
+
+    /// A completion that took `duration`, with the tokens it used if the provider reported them
+    pub fn record_completion(&self, provider: &str, model: &str, duration: Duration, ok: bool, usage: Option<&TokenUsage>) {
+        let cx = Context::current();
+        let labels = labels(provider, model);
+
+        let mut outcome = labels.clone();
+        outcome.push(KeyValue::new("outcome", if ok { "ok" } else { "error" }));
+        self.requests.add(&cx, 1, &outcome);
+        self.duration.record(&cx, duration.as_secs_f64(), &labels);
+
+        if let Some(usage) = usage {
+            self.prompt_tokens.add(&cx, usage.prompt_tokens, &labels);
+            self.completion_tokens.add(&cx, usage.completion_tokens, &labels);
+            if let Some(cost) = estimate_cost(provider, model, usage) {
+                self.cost.add(&cx, cost, &labels);
+            }
+        }
+    }
+
+    pub fn record_parse_failure(&self, provider: &str, model: &str) {
+        self.parse_failures.add(&Context::current(), 1, &labels(provider, model));
+    }
+
+    /// Suggestions that were stored as new cocktails and ones that were already there
+    pub fn record_cocktails(&self, provider: &str, model: &str, created: usize, duplicates: usize) {
+        let cx = Context::current();
+        for (result, count) in [("created", created), ("duplicate", duplicates)] {
+            let mut labels = labels(provider, model);
+            labels.push(KeyValue::new("result", result));
+            self.cocktails.add(&cx, count as u64, &labels);
+        }
+    }
+}
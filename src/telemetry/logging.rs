@@ -0,0 +1,26 @@
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogFormat, TelemetryConfig};
+
+/// Logs to stdout, filtered by `telemetry.log_filter` (e.g. `info,bartender=debug`). The json
+/// format writes a JSON object a line for log collectors, with the request_id of the request
+/// being handled.
+pub fn init_logging(config: &TelemetryConfig) {
+    // checked when the config was loaded
+    let filter = EnvFilter::try_new(&config.log_filter).unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    // also picks up the `log` records of actix and diesel
+    let result = if config.log_format == LogFormat::Json {
+        builder.json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .try_init()
+    } else {
+        builder.try_init()
+    };
+    if let Err(err) = result {
+        eprintln!("Could not set up logging: {}", err);
+    }
+}
@@ -0,0 +1,171 @@
+use actix_web_opentelemetry::{PrometheusMetricsHandler, RequestMetrics, RequestMetricsBuilder};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use opentelemetry::sdk::export::metrics::{aggregation, AggregatorSelector};
+use opentelemetry::sdk::metrics::aggregators::{self, Aggregator};
+use opentelemetry::sdk::metrics::sdk_api::{Descriptor, InstrumentKind};
+use opentelemetry::sdk::metrics::{controllers, processors};
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::trace::TraceError;
+use opentelemetry::{global, runtime, KeyValue};
+use opentelemetry_prometheus::PrometheusExporter;
+
+use crate::config::TelemetryConfig;
+use crate::models::stats::Counts;
+use crate::repository::database::Database;
+
+mod llm;
+mod logging;
+mod request_id;
+
+pub use self::llm::llm_metrics;
+pub use self::logging::init_logging;
+pub use self::request_id::RequestLogger;
+
+/// Buckets of the request duration histogram, in milliseconds
+const DURATION_BUCKETS: [f64; 10] = [5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0];
+/// How often the gauges count what is stored, scrapes only read the last counts
+const COUNTS_INTERVAL: Duration = Duration::from_secs(15);
+/// Buckets of the LLM latency histogram, in seconds
+const LLM_DURATION_BUCKETS: [f64; 9] = [0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0];
+
+/// Request metrics and the gauges on /metrics, plus tracing when a collector is configured.
+/// Create it once outside of `HttpServer::new` so that all workers share it.
+#[derive(Clone)]
+pub struct OpenTelemetryStack {
+    exporter: PrometheusExporter,
+    request_metrics: RequestMetrics,
+}
+
+impl OpenTelemetryStack {
+    /// Traces are only sent when `telemetry.jaeger_endpoint` is set, e.g. to
+    /// http://localhost:14268/api/traces for the Jaeger of telemetry.yaml
+    pub fn new(db: Database, config: &TelemetryConfig) -> OpenTelemetryStack {
+        let resource = Resource::new(vec![KeyValue::new("service.name", config.service_name.clone())]);
+        if let Some(endpoint) = &config.jaeger_endpoint {
+            match init_tracing(endpoint, resource.clone()) {
+                Ok(()) => tracing::info!(endpoint, "sending traces"),
+                Err(err) => tracing::error!(endpoint, error = %err, "could not set up tracing"),
+            }
+        }
+
+        let controller = controllers::basic(
+            processors::factory(
+                BucketSelector,
+                aggregation::cumulative_temporality_selector(),
+            )
+            .with_memory(true),
+        )
+        .with_resource(resource)
+        .build();
+        // also makes it the global meter provider
+        let exporter = opentelemetry_prometheus::exporter(controller).init();
+
+        let request_metrics = RequestMetricsBuilder::new().build(global::meter("actix_web"));
+        register_gauges(db);
+
+        OpenTelemetryStack { exporter, request_metrics }
+    }
+
+    /// Middleware recording the count and duration of requests
+    pub fn metrics(&self) -> RequestMetrics {
+        self.request_metrics.clone()
+    }
+
+    /// Serves everything in the Prometheus text format
+    pub fn metrics_handler(&self) -> PrometheusMetricsHandler {
+        PrometheusMetricsHandler::new(self.exporter.clone())
+    }
+
+    /// Sends the spans that haven't been exported yet
+    pub fn shutdown() {
+        global::shutdown_tracer_provider();
+    }
+}
+
+/// Like `selectors::simple::histogram`, with buckets that fit the histogram
+#[derive(Debug)]
+struct BucketSelector;
+
+impl AggregatorSelector for BucketSelector {
+    fn aggregator_for(&self, descriptor: &Descriptor) -> Option<Arc<dyn Aggregator + Send + Sync>> {
+        match descriptor.instrument_kind() {
+            InstrumentKind::GaugeObserver => Some(Arc::new(aggregators::last_value())),
+            InstrumentKind::Histogram if descriptor.name() == llm::LLM_DURATION => {
+                Some(Arc::new(aggregators::histogram(&LLM_DURATION_BUCKETS)))
+            },
+            InstrumentKind::Histogram => Some(Arc::new(aggregators::histogram(&DURATION_BUCKETS))),
+            _ => Some(Arc::new(aggregators::sum())),
+        }
+    }
+}
+
+fn init_tracing(endpoint: &str, resource: Resource) -> Result<(), TraceError> {
+    // the batches are sent from a thread of their own, not from the workers
+    let provider = opentelemetry_jaeger::new_collector_pipeline()
+        .with_endpoint(endpoint)
+        .with_reqwest()
+        .with_trace_config(trace::config().with_resource(resource))
+        .build_batch(runtime::TokioCurrentThread)?;
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider);
+    Ok(())
+}
+
+/// The last counts of what is stored
+#[derive(Default)]
+struct StoredCounts {
+    cocktails: AtomicU64,
+    ingredients: AtomicU64,
+    todos: AtomicU64,
+}
+
+/// Counts what is stored every `COUNTS_INTERVAL` from a thread of its own, /metrics is public
+/// and the queries would otherwise hold up an actix worker at each scrape
+fn spawn_counter(db: Database, counts: Arc<StoredCounts>) {
+    thread::Builder::new()
+        .name("gauge-counter".to_string())
+        .spawn(move || loop {
+            match Counts::get_counts(&db) {
+                Ok(x) => {
+                    counts.cocktails.store(x.cocktails as u64, Ordering::Relaxed);
+                    counts.ingredients.store(x.ingredients as u64, Ordering::Relaxed);
+                    counts.todos.store(x.todos as u64, Ordering::Relaxed);
+                },
+                Err(err) => tracing::error!(error = %err, "could not count rows for the gauges"),
+            }
+            thread::sleep(COUNTS_INTERVAL);
+        })
+        .expect("Failed to spawn the gauge counter");
+}
+
+/// Gauges of what is stored
+fn register_gauges(db: Database) {
+    let meter = global::meter("bartender");
+    let cocktails = meter.u64_observable_gauge("bartender_cocktails")
+        .with_description("Number of cocktails")
+        .init();
+    let ingredients = meter.u64_observable_gauge("bartender_ingredients")
+        .with_description("Number of distinct ingredients")
+        .init();
+    let todos = meter.u64_observable_gauge("bartender_todos")
+        .with_description("Number of todos")
+        .init();
+
+    let counts = Arc::new(StoredCounts::default());
+    spawn_counter(db, counts.clone());
+
+    let registered = meter.register_callback(move |cx| {
+        cocktails.observe(cx, counts.cocktails.load(Ordering::Relaxed), &[]);
+        ingredients.observe(cx, counts.ingredients.load(Ordering::Relaxed), &[]);
+        todos.observe(cx, counts.todos.load(Ordering::Relaxed), &[]);
+    });
+    if let Err(err) = registered {
+        tracing::error!(error = %err, "could not register the gauges");
+    }
+}
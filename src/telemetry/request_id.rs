@@ -0,0 +1,139 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Instant;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::error::InternalError;
+use actix_web::{Error, HttpMessage};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longest id taken from a client, longer ones are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// The id of a request, from its X-Request-Id header or a new uuid. It is in the request
+/// extensions and everything logged while handling the request carries it as `request_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Keeps the id the client (or a proxy in front of us) sent if it is sane
+    fn from_header(value: Option<&HeaderValue>) -> RequestId {
+        let sent = value
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty() && x.len() <= MAX_REQUEST_ID_LENGTH)
+            .filter(|x| x.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'));
+
+        match sent {
+            Some(id) => RequestId(id.to_string()),
+            None => RequestId(Uuid::new_v4().to_string()),
+        }
+    }
+}
+
+/// Middleware giving every request a `RequestId`, handling it in a span carrying the id and
+/// logging how it went. The id is sent back as X-Request-Id. Wrap it last so that it is the
+/// outermost and sees everything.
+pub struct RequestLogger;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestLogger
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestLoggerMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestLoggerMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RequestLoggerMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestLoggerMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = RequestId::from_header(req.headers().get(REQUEST_ID_HEADER));
+        req.extensions_mut().insert(request_id.clone());
+
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id.0,
+            method = %req.method(),
+            path = %req.path(),
+        );
+        let started = Instant::now();
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let result = service.call(req).await;
+            let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+            let header_value = HeaderValue::from_str(&request_id.0).ok();
+
+            // errors of the middlewares (auth, rate limits) only become responses further out,
+            // so the one they'd become is built here to log it and add the header
+            let mut res = match result {
+                Ok(res) => res,
+                Err(err) => {
+                    let mut response = err.error_response();
+                    log_response(response.status(), elapsed_ms, Some(&err));
+                    if let Some(value) = header_value {
+                        response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    return Err(InternalError::from_response(err, response).into());
+                },
+            };
+
+            log_response(res.status(), elapsed_ms, res.response().error());
+            if let Some(value) = header_value {
+                res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        }.instrument(span))
+    }
+}
+
+fn log_response(status: StatusCode, elapsed_ms: f64, error: Option<&Error>) {
+    match error {
+        Some(err) if status.is_server_error() => {
+            tracing::error!(status = status.as_u16(), elapsed_ms, error = %err, "request failed");
+        },
+        Some(err) => tracing::info!(status = status.as_u16(), elapsed_ms, error = %err, "request failed"),
+        None => tracing::info!(status = status.as_u16(), elapsed_ms, "request finished"),
+    }
+}
+
+
+#[test]
+pub fn test_request_id_from_header() {
+    let sent = HeaderValue::from_static("abc-123_x.y");
+    assert_eq!(RequestId::from_header(Some(&sent)).0, "abc-123_x.y");
+
+    let generated = RequestId::from_header(None);
+    assert!(Uuid::parse_str(&generated.0).is_ok());
+
+    let evil = HeaderValue::from_static("abc\tdef");
+    assert_ne!(RequestId::from_header(Some(&evil)).0, "abc\tdef");
+    let long = HeaderValue::from_str(&"a".repeat(65)).unwrap();
+    assert_eq!(RequestId::from_header(Some(&long)).0.len(), 36);
+}